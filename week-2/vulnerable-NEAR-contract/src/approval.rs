@@ -0,0 +1,52 @@
+use near_sdk::json_types::U64;
+use near_sdk::{env, near};
+
+use crate::{Contract, ContractExt, Id};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expiry {
+    /// block timestamp in nanoseconds
+    Timestamp(U64),
+    BlockHeight(U64),
+}
+
+impl Expiry {
+    pub fn is_expired(&self) -> bool {
+        match self {
+            Expiry::Timestamp(ts) => env::block_timestamp() >= ts.0,
+            Expiry::BlockHeight(height) => env::block_height() >= height.0,
+        }
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn approval_expiry(&self, id: Id) -> Option<Expiry> {
+        self.approval_expiries.get(&id).copied()
+    }
+
+    /// Removes every expired approval, callable by anyone. Returns the number pruned.
+    pub fn prune_expired_approvals(&mut self) -> u32 {
+        let mut pruned = 0;
+        // ids wrap at `Id::MAX`, so never scan past it
+        let last = self.supply.min(Id::MAX as u16 + 1);
+        for id in 0..last {
+            let id = id as Id;
+            if self.approval_expired(id) {
+                self.approvals.remove(&id);
+                self.approval_expiries.remove(&id);
+                pruned += 1;
+            }
+        }
+        pruned
+    }
+}
+
+impl Contract {
+    pub(crate) fn approval_expired(&self, id: Id) -> bool {
+        self.approval_expiries
+            .get(&id)
+            .is_some_and(|expiry| expiry.is_expired())
+    }
+}
@@ -0,0 +1,93 @@
+use near_sdk::{env, near, require, AccountId, Gas};
+
+use crate::events::{self, NftMintLog, NftTransferLog};
+use crate::roles::Role;
+use crate::{Contract, ContractExt, Id};
+
+/// hard cap on entries per call, events are batched into one log line
+pub const MAX_BATCH_SIZE: usize = 100;
+/// gas budgeted for processing a single entry
+const BATCH_ITEM_GAS: Gas = Gas::from_tgas(2);
+/// storage writes are flushed when the call ends, reserve their cost per processed entry
+const BATCH_FLUSH_GAS: Gas = Gas::from_ggas(500);
+/// left for serializing the outcome and emitting the event
+const BATCH_RESERVE_GAS: Gas = Gas::from_tgas(5);
+
+#[near(serializers = [json])]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BatchOutcome {
+    /// ids minted or transferred, in entry order
+    pub succeeded: Vec<Id>,
+    /// ids skipped because the caller was not allowed to move them
+    pub failed: Vec<Id>,
+    /// number of entries handled, resubmit `entries[processed..]` if lower than the input length
+    pub processed: u32,
+}
+
+#[near]
+impl Contract {
+    /// Mints one token to every receiver, stopping early when prepaid gas runs low.
+    pub fn mint_batch(&mut self, receivers: Vec<AccountId>) -> BatchOutcome {
+        self.assert_not_paused();
+        self.assert_role(Role::Minter);
+        require!(receivers.len() <= MAX_BATCH_SIZE, "batch too large!");
+
+        let mut outcome = BatchOutcome::default();
+        let mut logs = Vec::new();
+        for receiver in receivers {
+            if !has_gas_for_next(outcome.processed) {
+                break;
+            }
+            let id = self.internal_mint(receiver.clone(), None, false);
+            logs.push(NftMintLog {
+                owner_id: receiver,
+                token_ids: vec![id.to_string()],
+            });
+            outcome.succeeded.push(id);
+            outcome.processed += 1;
+        }
+        events::nft_mint_batch(logs);
+        outcome
+    }
+
+    /// Transfers every `(id, receiver)` entry the caller owns or is approved for,
+    /// stopping early when prepaid gas runs low.
+    pub fn transfer_batch(&mut self, entries: Vec<(Id, AccountId)>) -> BatchOutcome {
+        self.assert_not_paused();
+        require!(entries.len() <= MAX_BATCH_SIZE, "batch too large!");
+
+        let sender = env::predecessor_account_id();
+        let mut outcome = BatchOutcome::default();
+        let mut logs = Vec::new();
+        for (id, receiver) in entries {
+            if !has_gas_for_next(outcome.processed) {
+                break;
+            }
+            outcome.processed += 1;
+            let old_owner = match self.check_owner_or_approved(id) {
+                Ok(owner) if !self.frozen.contains(&id) && self.is_transferable(id) => owner,
+                _ => {
+                    outcome.failed.push(id);
+                    continue;
+                }
+            };
+            self.tokens.insert(id, receiver.clone());
+            logs.push(NftTransferLog {
+                authorized_id: (sender != old_owner).then(|| sender.clone()),
+                old_owner_id: old_owner,
+                new_owner_id: receiver,
+                token_ids: vec![id.to_string()],
+            });
+            outcome.succeeded.push(id);
+        }
+        events::nft_transfer_batch(logs);
+        outcome
+    }
+}
+
+fn has_gas_for_next(processed: u32) -> bool {
+    let needed = BATCH_ITEM_GAS.as_gas()
+        + BATCH_FLUSH_GAS.as_gas() * (processed as u64 + 1)
+        + BATCH_RESERVE_GAS.as_gas();
+    env::used_gas().as_gas() + needed <= env::prepaid_gas().as_gas()
+}
@@ -0,0 +1,36 @@
+use near_sdk::FunctionError;
+
+#[derive(FunctionError, Debug, PartialEq, Eq)]
+pub enum ContractError {
+    TokenNotFound,
+    NotOwner,
+    NotOwnerOrApproved,
+    ApprovalExpired,
+    NonTransferable,
+    SaleNotActive,
+    NotAllowlisted,
+    MintLimitReached,
+    InsufficientDeposit,
+    RoyaltyTooHigh,
+    TooManyRoyaltyAccounts,
+    PayoutTooLong,
+}
+
+impl std::fmt::Display for ContractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContractError::TokenNotFound => write!(f, "token not found!"),
+            ContractError::NotOwner => write!(f, "not owner!"),
+            ContractError::NotOwnerOrApproved => write!(f, "not owner or approved!"),
+            ContractError::ApprovalExpired => write!(f, "approval expired!"),
+            ContractError::NonTransferable => write!(f, "token is not transferable!"),
+            ContractError::SaleNotActive => write!(f, "sale not active!"),
+            ContractError::NotAllowlisted => write!(f, "not allowlisted!"),
+            ContractError::MintLimitReached => write!(f, "mint limit reached!"),
+            ContractError::InsufficientDeposit => write!(f, "insufficient deposit!"),
+            ContractError::RoyaltyTooHigh => write!(f, "royalty exceeds 100%!"),
+            ContractError::TooManyRoyaltyAccounts => write!(f, "too many royalty accounts!"),
+            ContractError::PayoutTooLong => write!(f, "payout exceeds max_len_payout!"),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use near_sdk::{near, AccountId};
+
+use crate::roles::Role;
+use crate::Id;
+
+#[near(event_json(standard = "roles"))]
+pub enum RoleEvent {
+    #[event_version("1.0.0")]
+    RoleGranted {
+        role: Role,
+        account: AccountId,
+        sender: AccountId,
+    },
+    #[event_version("1.0.0")]
+    RoleRevoked {
+        role: Role,
+        account: AccountId,
+        sender: AccountId,
+    },
+}
+
+#[near(serializers = [json])]
+#[derive(Debug)]
+pub struct NftMintLog {
+    pub owner_id: AccountId,
+    pub token_ids: Vec<String>,
+}
+
+#[near(serializers = [json])]
+#[derive(Debug)]
+pub struct NftTransferLog {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorized_id: Option<AccountId>,
+    pub old_owner_id: AccountId,
+    pub new_owner_id: AccountId,
+    pub token_ids: Vec<String>,
+}
+
+#[near(serializers = [json])]
+#[derive(Debug)]
+pub struct NftApproveLog {
+    pub owner_id: AccountId,
+    pub approved_account_id: AccountId,
+    pub token_ids: Vec<String>,
+}
+
+#[near(serializers = [json])]
+#[derive(Debug)]
+pub struct NftBurnLog {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorized_id: Option<AccountId>,
+    pub owner_id: AccountId,
+    pub token_ids: Vec<String>,
+}
+
+// variant names become the NEP-171 event names, so the `Nft` prefix is required
+#[allow(clippy::enum_variant_names)]
+#[near(event_json(standard = "nep171"))]
+pub enum NftEvent {
+    #[event_version("1.0.0")]
+    NftMint(Vec<NftMintLog>),
+    #[event_version("1.0.0")]
+    NftTransfer(Vec<NftTransferLog>),
+    #[event_version("1.0.0")]
+    NftBurn(Vec<NftBurnLog>),
+}
+
+#[near(event_json(standard = "nep178"))]
+pub enum NftApprovalEvent {
+    #[event_version("1.0.0")]
+    NftApprove(Vec<NftApproveLog>),
+}
+
+pub(crate) fn nft_mint(owner_id: AccountId, id: Id) {
+    NftEvent::NftMint(vec![NftMintLog {
+        owner_id,
+        token_ids: vec![id.to_string()],
+    }])
+    .emit();
+}
+
+pub(crate) fn nft_transfer(
+    authorized_id: Option<AccountId>,
+    old_owner_id: AccountId,
+    new_owner_id: AccountId,
+    id: Id,
+) {
+    NftEvent::NftTransfer(vec![NftTransferLog {
+        authorized_id,
+        old_owner_id,
+        new_owner_id,
+        token_ids: vec![id.to_string()],
+    }])
+    .emit();
+}
+
+pub(crate) fn nft_mint_batch(logs: Vec<NftMintLog>) {
+    if !logs.is_empty() {
+        NftEvent::NftMint(logs).emit();
+    }
+}
+
+pub(crate) fn nft_transfer_batch(logs: Vec<NftTransferLog>) {
+    if !logs.is_empty() {
+        NftEvent::NftTransfer(logs).emit();
+    }
+}
+
+pub(crate) fn nft_burn(authorized_id: Option<AccountId>, owner_id: AccountId, id: Id) {
+    NftEvent::NftBurn(vec![NftBurnLog {
+        authorized_id,
+        owner_id,
+        token_ids: vec![id.to_string()],
+    }])
+    .emit();
+}
+
+pub(crate) fn nft_approve(owner_id: AccountId, approved_account_id: AccountId, id: Id) {
+    NftApprovalEvent::NftApprove(vec![NftApproveLog {
+        owner_id,
+        approved_account_id,
+        token_ids: vec![id.to_string()],
+    }])
+    .emit();
+}
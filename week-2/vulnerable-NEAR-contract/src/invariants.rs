@@ -0,0 +1,155 @@
+//! Test-only invariant checks, run after every step of randomized action sequences.
+
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{testing_env, AccountId};
+
+use crate::{Contract, Id, Role};
+
+/// Panics with a description of the first broken invariant.
+pub(crate) fn check_invariants(contract: &Contract) {
+    // every allocated id must be representable, otherwise mints overwrite old tokens
+    assert!(
+        contract.supply <= Id::MAX as u16 + 1,
+        "supply wrapped: {} ids allocated",
+        contract.supply
+    );
+
+    // walk every id, approvals left past supply or on burned ids must be caught too
+    let mut owned = 0u64;
+    for id in Id::MIN..=Id::MAX {
+        let has_owner = contract.tokens.get(&id).is_some();
+        if has_owner {
+            owned += 1;
+        }
+        if u16::from(id) < contract.supply {
+            assert!(has_owner, "token {id} below supply has no owner");
+        }
+        assert!(
+            has_owner || contract.approvals.get(&id).is_none(),
+            "approval for missing token {id}"
+        );
+        assert!(
+            contract.approvals.get(&id).is_some() || contract.approval_expiries.get(&id).is_none(),
+            "expiry without approval for token {id}"
+        );
+    }
+
+    assert_eq!(
+        contract.minted - contract.burned,
+        owned,
+        "circulating supply does not match owned tokens"
+    );
+    assert!(
+        contract.has_role(Role::Admin, contract.owner.clone()),
+        "owner lost the admin role"
+    );
+}
+
+/// xorshift64, deterministic per seed so failures can be replayed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Mint,
+    Approve,
+    Transfer,
+}
+
+fn accounts() -> Vec<AccountId> {
+    ["admin.near", "bob.near", "carol.near", "dave.near"]
+        .iter()
+        .map(|a| a.parse().unwrap())
+        .collect()
+}
+
+fn set_context(predecessor: AccountId) {
+    let mut builder = VMContextBuilder::new();
+    builder.predecessor_account_id(predecessor);
+    testing_env!(builder.build());
+}
+
+/// Runs `steps` random actions from `actions`, checking invariants after each one.
+fn run_sequence(seed: u64, steps: usize, actions: &[Action]) {
+    let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1);
+    let accounts = accounts();
+    let admin = accounts[0].clone();
+
+    // `testing_env!` carries storage over, start every sequence from an empty trie
+    near_sdk::mock::with_mocked_blockchain(|b| b.take_storage());
+    set_context(admin.clone());
+    let mut contract = Contract::init(admin.clone(), None);
+    for account in &accounts {
+        set_context(admin.clone());
+        contract.grant_role(Role::Minter, account.clone());
+    }
+    check_invariants(&contract);
+
+    for _ in 0..steps {
+        let caller = rng.pick(&accounts).clone();
+        // ids past supply exercise the missing token paths
+        let id = rng.below(contract.supply as u64 + 2) as Id;
+        let target = rng.pick(&accounts).clone();
+        // a fresh context per call, like separate transactions
+        set_context(caller);
+        match *rng.pick(actions) {
+            Action::Mint => {
+                contract.mint(None, None).unwrap();
+            }
+            Action::Approve => {
+                let _ = contract.approve(id, target, None);
+            }
+            Action::Transfer => {
+                let _ = contract.transfer(id, target);
+            }
+        }
+        check_invariants(&contract);
+    }
+}
+
+#[test]
+fn random_sequences_keep_invariants() {
+    let actions = [Action::Mint, Action::Approve, Action::Transfer];
+    for seed in 0..10 {
+        run_sequence(seed, 50, &actions);
+    }
+}
+
+#[test]
+#[should_panic(expected = "supply wrapped")]
+fn invariants_catch_mint_overflow() {
+    // the same bug as `exploit_mint_overflow`, found without knowing the exploit
+    let actions = [
+        Action::Mint,
+        Action::Mint,
+        Action::Approve,
+        Action::Transfer,
+    ];
+    run_sequence(7, 1_000, &actions);
+}
+
+#[test]
+#[should_panic(expected = "approval for missing token 200")]
+fn invariants_catch_approval_past_supply() {
+    let admin: AccountId = "admin.near".parse().unwrap();
+    set_context(admin.clone());
+    let mut contract = Contract::init(admin.clone(), None);
+    contract.approvals.insert(200, admin);
+    check_invariants(&contract);
+}
@@ -1,26 +1,63 @@
-use near_sdk::store::LookupMap;
-use near_sdk::{env, near, require, AccountId};
+use near_sdk::store::{LookupMap, LookupSet};
+use near_sdk::{env, near, AccountId, NearToken, Promise};
+
+mod approval;
+mod batch;
+mod errors;
+mod events;
+#[cfg(test)]
+mod invariants;
+mod pause;
+mod roles;
+mod royalty;
+mod sale;
+mod soulbound;
+mod supply;
+mod transfer_call;
+mod upgrade;
+mod views;
+
+pub use crate::approval::Expiry;
+pub use crate::batch::BatchOutcome;
+pub use crate::errors::ContractError;
+pub use crate::roles::Role;
+pub use crate::royalty::{Payout, Royalty};
+pub use crate::sale::{SaleConfig, SalePhase};
+pub use crate::supply::{StorageDeposit, SupplyInfo};
+pub use crate::transfer_call::NftReceiver;
+pub use crate::upgrade::STATE_VERSION;
+pub use crate::views::{TokenApproval, TokenOwner};
 
 pub type Id = u8;
 
 #[near(contract_state)]
 pub struct Contract {
+    /// always [`STATE_VERSION`], kept first so `migrate` can match on it
+    pub version: u8,
     pub tokens: LookupMap<Id, AccountId>,
     pub approvals: LookupMap<Id, AccountId>,
     pub supply: u16,
+    pub owner: AccountId,
+    pub roles: LookupSet<(Role, AccountId)>,
+    pub paused: bool,
+    pub frozen: LookupSet<Id>,
+    pub royalties: LookupMap<Id, Royalty>,
+    pub minted: u64,
+    pub burned: u64,
+    pub approval_expiries: LookupMap<Id, Expiry>,
+    /// collection-wide switch, `false` makes every token soulbound
+    pub transferable: bool,
+    pub non_transferable: LookupSet<Id>,
+    pub sale: Option<SaleConfig>,
+    pub sale_minted: LookupMap<AccountId, u32>,
+    /// sale payments not yet withdrawn, in yoctoNEAR
+    pub proceeds: u128,
+    pub storage_deposits: LookupMap<Id, StorageDeposit>,
 }
 
 impl Default for Contract {
     fn default() -> Self {
-        Self {
-            tokens: {
-                let mut a = LookupMap::new(b"tokens".to_vec());
-                a.insert(0, "admin.near".parse().unwrap());
-                a
-            },
-            approvals: LookupMap::new(b"approvals".to_vec()),
-            supply: 1,
-        }
+        Self::init("admin.near".parse().unwrap(), None)
     }
 }
 
@@ -28,15 +65,37 @@ impl Default for Contract {
 impl Contract {
     #[init]
     #[private] // only callable by the contract's account
-    pub fn init(admin: AccountId) -> Self {
+    pub fn init(admin: AccountId, transferable: Option<bool>) -> Self {
+        events::nft_mint(admin.clone(), 0);
         Self {
+            version: STATE_VERSION,
             tokens: {
                 let mut a = LookupMap::new(b"tokens".to_vec());
-                a.insert(0, admin);
+                a.insert(0, admin.clone());
                 a
             },
             approvals: LookupMap::new(b"approvals".to_vec()),
             supply: 1,
+            roles: {
+                let mut r = LookupSet::new(b"roles".to_vec());
+                r.insert((Role::Admin, admin.clone()));
+                r.insert((Role::Minter, admin.clone()));
+                r.insert((Role::Pauser, admin.clone()));
+                r
+            },
+            owner: admin,
+            paused: false,
+            frozen: LookupSet::new(b"frozen".to_vec()),
+            royalties: LookupMap::new(b"royalties".to_vec()),
+            minted: 1,
+            burned: 0,
+            approval_expiries: LookupMap::new(b"approval_expiries".to_vec()),
+            transferable: transferable.unwrap_or(true),
+            non_transferable: LookupSet::new(b"non_transferable".to_vec()),
+            sale: None,
+            sale_minted: LookupMap::new(b"sale_minted".to_vec()),
+            proceeds: 0,
+            storage_deposits: LookupMap::new(b"storage_deposits".to_vec()),
         }
     }
 
@@ -44,38 +103,130 @@ impl Contract {
         self.tokens.get(&id).cloned()
     }
 
-    pub fn mint(&mut self) -> Id {
-        self.tokens
-            .insert(self.supply.to_le_bytes()[0], env::predecessor_account_id());
-        let id = self.supply;
-        self.supply += 1;
-        id as Id
+    /// Mints a token to the caller. The attached deposit pays for its storage and is
+    /// refunded on `burn`, anything above the storage cost is returned right away.
+    #[payable]
+    #[handle_result]
+    pub fn mint(
+        &mut self,
+        royalty: Option<Royalty>,
+        transferable: Option<bool>,
+    ) -> Result<Id, ContractError> {
+        self.assert_not_paused();
+        self.assert_role(Role::Minter);
+        if let Some(royalty) = &royalty {
+            royalty::validate_royalty(royalty)?;
+        }
+        let minter = env::predecessor_account_id();
+        let (id, refund) = self.internal_mint_paid(
+            minter.clone(),
+            royalty,
+            transferable == Some(false),
+            minter.clone(),
+            env::attached_deposit().as_yoctonear(),
+        );
+        events::nft_mint(minter.clone(), id);
+        if refund > 0 {
+            Promise::new(minter)
+                .transfer(NearToken::from_yoctonear(refund))
+                .detach();
+        }
+        Ok(id)
     }
 
-    pub fn approve(&mut self, id: Id, delegatee: AccountId) {
-        require!(
-            self.tokens.get(&id).unwrap().clone() == env::predecessor_account_id(),
-            "not owner!"
-        );
-        self.approvals.insert(id, delegatee);
+    #[handle_result]
+    pub fn approve(
+        &mut self,
+        id: Id,
+        delegatee: AccountId,
+        expiry: Option<Expiry>,
+    ) -> Result<(), ContractError> {
+        self.assert_not_paused();
+        self.assert_not_frozen(id);
+        let owner = self.tokens.get(&id).ok_or(ContractError::TokenNotFound)?;
+        self.check_transferable(id)?;
+        if *owner != env::predecessor_account_id() {
+            return Err(ContractError::NotOwner);
+        }
+        self.approvals.insert(id, delegatee.clone());
+        match expiry {
+            Some(expiry) => self.approval_expiries.insert(id, expiry),
+            None => self.approval_expiries.remove(&id),
+        };
+        events::nft_approve(env::predecessor_account_id(), delegatee, id);
+        Ok(())
     }
 
-    pub fn transfer(&mut self, id: Id, receiver: AccountId) {
-        require!(
-            self.tokens.get(&id).unwrap().clone() == env::predecessor_account_id()
-                || self.approvals.get(&id).unwrap().clone() == env::predecessor_account_id(),
-            "not owner!"
-        );
-        self.tokens.insert(id, receiver);
+    #[handle_result]
+    pub fn transfer(&mut self, id: Id, receiver: AccountId) -> Result<(), ContractError> {
+        self.assert_not_paused();
+        self.assert_not_frozen(id);
+        let old_owner = self.check_owner_or_approved(id)?;
+        self.check_transferable(id)?;
+        let sender = env::predecessor_account_id();
+        self.tokens.insert(id, receiver.clone());
+        let authorized_id = (sender != old_owner).then_some(sender);
+        events::nft_transfer(authorized_id, old_owner, receiver, id);
+        Ok(())
     }
 }
 
-use near_sdk::log;
+impl Contract {
+    /// Allocates the next id to `owner`, without any access or event checks.
+    pub(crate) fn internal_mint(
+        &mut self,
+        owner: AccountId,
+        royalty: Option<Royalty>,
+        soulbound: bool,
+    ) -> Id {
+        self.tokens.insert(self.supply.to_le_bytes()[0], owner);
+        match royalty {
+            Some(royalty) => self.royalties.insert(self.supply.to_le_bytes()[0], royalty),
+            None => self.royalties.remove(&self.supply.to_le_bytes()[0]),
+        };
+        if soulbound {
+            self.non_transferable.insert(self.supply.to_le_bytes()[0]);
+        } else {
+            self.non_transferable.remove(&self.supply.to_le_bytes()[0]);
+        }
+        let id = self.supply;
+        self.supply += 1;
+        self.minted += 1;
+        id as Id
+    }
+
+    /// Returns the token owner if the caller is the owner or its approved delegatee.
+    pub(crate) fn check_owner_or_approved(&self, id: Id) -> Result<AccountId, ContractError> {
+        let owner = self
+            .tokens
+            .get(&id)
+            .cloned()
+            .ok_or(ContractError::TokenNotFound)?;
+        let sender = env::predecessor_account_id();
+        // the owner never needs an approval, so only look it up for other callers
+        if owner != sender {
+            if self.approvals.get(&id) != Some(&sender) {
+                return Err(ContractError::NotOwnerOrApproved);
+            }
+            if self.approval_expired(id) {
+                return Err(ContractError::ApprovalExpired);
+            }
+        }
+        Ok(owner)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+    use crate::upgrade::ContractV0;
+    use near_sdk::json_types::{Base58CryptoHash, U128};
+    use near_sdk::CryptoHash;
+    use near_sdk::{
+        mock::MockAction,
+        test_utils::{get_created_receipts, get_logs, VMContextBuilder},
+        testing_env, Gas, NearToken,
+    };
 
     #[test]
     fn exploit_mint_overflow() {
@@ -83,12 +234,15 @@ mod tests {
         set_context(bob.clone());
         // init
         let admin: AccountId = "admin.near".parse().unwrap();
-        let mut contract = Contract::init(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
         assert_eq!(contract.owner_of(0).unwrap(), admin);
+        grant_minter(&mut contract, &admin, &bob);
 
         // create a mint loop until the supply is 256
-        for i in 0..256 {
-            contract.mint();
+        for _ in 0..256 {
+            // one call per mint, each call has its own log limit
+            set_context(bob.clone());
+            contract.mint(None, None).unwrap();
         }
         println!("Mint loop completed!");
         assert_eq!(contract.supply, 257);
@@ -101,28 +255,917 @@ mod tests {
         set_context(bob.clone());
         // init
         let admin: AccountId = "admin.near".parse().unwrap();
-        let mut contract = Contract::init(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
         assert_eq!(contract.owner_of(0).unwrap(), admin);
+        grant_minter(&mut contract, &admin, &bob);
 
         // mint a new NFT
-        let id = contract.mint();
+        let id = contract.mint(None, None).unwrap();
         // check the owner of the NFT
         assert_eq!(contract.owner_of(id).unwrap(), bob);
 
         // approve the bob
-        contract.approve(id, bob.clone());
+        contract.approve(id, bob.clone(), None).unwrap();
         // transfer the NFT to the admin
-        contract.transfer(id, admin.clone());
+        contract.transfer(id, admin.clone()).unwrap();
         // check the owner of the NFT
         assert_eq!(contract.owner_of(id).unwrap(), admin);
         // check the approval of the NFT
         assert_eq!(contract.approvals.get(&id).unwrap().clone(), bob);
         // transfer the NFT back to bob
-        contract.transfer(id, bob.clone());
+        contract.transfer(id, bob.clone()).unwrap();
         // check the owner of the NFT
         assert_eq!(contract.owner_of(id).unwrap(), bob);
     }
 
+    #[test]
+    fn init_seeds_admin_roles() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let contract = Contract::init(admin.clone(), None);
+        assert_eq!(contract.contract_owner(), admin);
+        assert!(contract.has_role(Role::Admin, admin.clone()));
+        assert!(contract.has_role(Role::Minter, admin.clone()));
+        assert!(contract.has_role(Role::Pauser, admin));
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role!")]
+    fn mint_requires_minter_role() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        set_context(bob);
+        let mut contract = Contract::init("admin.near".parse().unwrap(), None);
+        contract.mint(None, None).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role!")]
+    fn grant_role_requires_admin() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        set_context(bob.clone());
+        let mut contract = Contract::init("admin.near".parse().unwrap(), None);
+        contract.grant_role(Role::Minter, bob);
+    }
+
+    #[test]
+    fn revoke_role_removes_minter() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+        grant_minter(&mut contract, &admin, &bob);
+        assert!(contract.has_role(Role::Minter, bob.clone()));
+
+        set_context(admin);
+        contract.revoke_role(Role::Minter, bob.clone());
+        assert!(!contract.has_role(Role::Minter, bob));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot revoke admin from owner!")]
+    fn owner_keeps_admin_role() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+        contract.revoke_role(Role::Admin, admin);
+    }
+
+    #[test]
+    fn pause_blocks_transfer_but_not_views() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+        contract.pause();
+        assert!(contract.is_paused());
+        assert_eq!(contract.owner_of(0).unwrap(), admin);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.transfer(0, "bob.near".parse().unwrap())
+        }));
+        assert!(result.is_err());
+
+        contract.unpause();
+        contract.transfer(0, "bob.near".parse().unwrap()).unwrap();
+        assert_eq!(contract.owner_of(0).unwrap().as_str(), "bob.near");
+    }
+
+    #[test]
+    #[should_panic(expected = "contract paused!")]
+    fn pause_blocks_mint() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin, None);
+        contract.pause();
+        contract.mint(None, None).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role!")]
+    fn pause_requires_pauser_role() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        set_context(bob);
+        let mut contract = Contract::init("admin.near".parse().unwrap(), None);
+        contract.pause();
+    }
+
+    #[test]
+    #[should_panic(expected = "token frozen!")]
+    fn frozen_token_cannot_be_approved() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin, None);
+        contract.freeze(0);
+        assert!(contract.is_frozen(0));
+        contract
+            .approve(0, "bob.near".parse().unwrap(), None)
+            .unwrap();
+    }
+
+    #[test]
+    fn mint_emits_nft_mint_event() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin, None);
+        let id = contract.mint(None, None).unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_mint","data":[{"owner_id":"admin.near","token_ids":["0"]}]}"#,
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_mint","data":[{"owner_id":"admin.near","token_ids":["1"]}]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn approve_emits_nft_approve_event() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin);
+        contract
+            .approve(0, "bob.near".parse().unwrap(), None)
+            .unwrap();
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep178","version":"1.0.0","event":"nft_approve","data":[{"owner_id":"admin.near","approved_account_id":"bob.near","token_ids":["0"]}]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn transfer_emits_nft_transfer_event() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin.clone());
+        contract.approve(0, bob.clone(), None).unwrap();
+
+        // owner transfer, no authorized_id
+        set_context(admin.clone());
+        contract.transfer(0, "carol.near".parse().unwrap()).unwrap();
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"old_owner_id":"admin.near","new_owner_id":"carol.near","token_ids":["0"]}]}"#,
+            ]
+        );
+
+        // approved transfer, authorized_id is the delegatee
+        set_context(bob);
+        contract.transfer(0, admin).unwrap();
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"authorized_id":"bob.near","old_owner_id":"carol.near","new_owner_id":"admin.near","token_ids":["0"]}]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn transfer_by_owner_without_approval() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin, None);
+        assert_eq!(contract.transfer(0, "bob.near".parse().unwrap()), Ok(()));
+        assert_eq!(contract.owner_of(0).unwrap().as_str(), "bob.near");
+    }
+
+    #[test]
+    fn missing_token_returns_error() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+        assert_eq!(
+            contract.approve(42, admin.clone(), None),
+            Err(ContractError::TokenNotFound)
+        );
+        assert_eq!(
+            contract.transfer(42, admin),
+            Err(ContractError::TokenNotFound)
+        );
+    }
+
+    #[test]
+    fn stranger_cannot_approve_or_transfer() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::init("admin.near".parse().unwrap(), None);
+        set_context(bob.clone());
+        assert_eq!(
+            contract.approve(0, bob.clone(), None),
+            Err(ContractError::NotOwner)
+        );
+        assert_eq!(
+            contract.transfer(0, bob),
+            Err(ContractError::NotOwnerOrApproved)
+        );
+    }
+
+    #[test]
+    fn mint_rejects_royalty_above_100_percent() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+        let royalty = Royalty::from([(admin, 6_000), ("bob.near".parse().unwrap(), 4_001)]);
+        assert_eq!(
+            contract.mint(Some(royalty), None),
+            Err(ContractError::RoyaltyTooHigh)
+        );
+    }
+
+    #[test]
+    fn nft_payout_splits_balance() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+        let royalty = Royalty::from([(bob.clone(), 1_000), (carol.clone(), 250)]);
+        let id = contract.mint(Some(royalty), None).unwrap();
+
+        let payout = contract
+            .nft_payout(id.to_string(), U128(1_000_000), Some(3))
+            .unwrap();
+        assert_eq!(payout.payout[&bob], U128(100_000));
+        assert_eq!(payout.payout[&carol], U128(25_000));
+        assert_eq!(payout.payout[&admin], U128(875_000));
+
+        assert_eq!(
+            contract.nft_payout(id.to_string(), U128(1_000_000), Some(2)),
+            Err(ContractError::PayoutTooLong)
+        );
+    }
+
+    #[test]
+    fn nft_transfer_payout_moves_token() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+        let id = contract
+            .mint(Some(Royalty::from([(bob.clone(), 500)])), None)
+            .unwrap();
+
+        let mut builder = VMContextBuilder::new();
+        builder
+            .predecessor_account_id(admin.clone())
+            .attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(builder.build());
+        let payout = contract
+            .nft_transfer_payout(bob.clone(), id.to_string(), None, None, U128(100), None)
+            .unwrap();
+        assert_eq!(payout.payout[&bob], U128(5));
+        assert_eq!(payout.payout[&admin], U128(95));
+        assert_eq!(contract.owner_of(id).unwrap(), bob);
+    }
+
+    #[test]
+    fn burn_removes_token_and_updates_supply() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+        let id = contract.mint(None, None).unwrap();
+        contract.approve(id, bob.clone(), None).unwrap();
+
+        // the approved delegatee may burn
+        set_context(bob);
+        contract.burn(id).unwrap();
+        assert_eq!(contract.owner_of(id), None);
+        assert!(contract.approvals.get(&id).is_none());
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_burn","data":[{"authorized_id":"bob.near","owner_id":"admin.near","token_ids":["1"]}]}"#,
+            ]
+        );
+        assert_eq!(
+            contract.supply_info(),
+            SupplyInfo {
+                minted: 2.into(),
+                burned: 1.into(),
+                circulating: 1.into(),
+                next_id: 2,
+            }
+        );
+        assert_eq!(contract.burn(id), Err(ContractError::TokenNotFound));
+    }
+
+    #[test]
+    fn burn_refunds_only_the_recorded_deposit_to_its_payer() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+
+        let attached = NearToken::from_near(1);
+        let mut builder = VMContextBuilder::new();
+        builder
+            .predecessor_account_id(admin.clone())
+            .attached_deposit(attached);
+        testing_env!(builder.build());
+        let storage_before = env::storage_usage();
+        let id = contract.mint(None, None).unwrap();
+        let deposit = contract.storage_deposits.get(&id).unwrap().amount;
+        // covers every byte the mint added, the deposit record included
+        assert_eq!(
+            deposit,
+            env::storage_byte_cost().as_yoctonear()
+                * u128::from(env::storage_usage() - storage_before)
+        );
+        assert_eq!(
+            transfers(),
+            vec![(admin.clone(), attached.as_yoctonear() - deposit)]
+        );
+        contract.transfer(id, bob.clone()).unwrap();
+
+        // the deposit goes back to the minter, not to whoever burns
+        set_context(bob.clone());
+        contract.burn(id).unwrap();
+        assert_eq!(transfers(), vec![(admin.clone(), deposit)]);
+        assert!(contract.storage_deposits.get(&id).is_none());
+
+        // token 0 came from `init` and was never paid for
+        set_context(admin.clone());
+        contract.transfer(0, bob.clone()).unwrap();
+        set_context(bob);
+        contract.burn(0).unwrap();
+        assert_eq!(transfers(), vec![]);
+    }
+
+    #[test]
+    fn stranger_cannot_burn() {
+        let mut contract = Contract::init("admin.near".parse().unwrap(), None);
+        set_context("bob.near".parse().unwrap());
+        assert_eq!(contract.burn(0), Err(ContractError::NotOwnerOrApproved));
+        assert_eq!(contract.supply_info().burned, 0.into());
+    }
+
+    #[test]
+    fn mint_batch_mints_to_receivers() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin);
+        let outcome = contract.mint_batch(vec![bob.clone(), carol.clone()]);
+        assert_eq!(
+            outcome,
+            BatchOutcome {
+                succeeded: vec![1, 2],
+                failed: vec![],
+                processed: 2,
+            }
+        );
+        assert_eq!(contract.owner_of(1).unwrap(), bob);
+        assert_eq!(contract.owner_of(2).unwrap(), carol);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_mint","data":[{"owner_id":"bob.near","token_ids":["1"]},{"owner_id":"carol.near","token_ids":["2"]}]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn mint_batch_stops_before_running_out_of_gas() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        let mut builder = VMContextBuilder::new();
+        builder
+            .predecessor_account_id(admin.clone())
+            .prepaid_gas(Gas::from_tgas(20));
+        testing_env!(builder.build());
+
+        let outcome = contract.mint_batch(vec![admin; 50]);
+        assert!(outcome.processed > 0 && outcome.processed < 50);
+        assert_eq!(outcome.succeeded.len(), outcome.processed as usize);
+        assert_eq!(contract.supply, outcome.processed as u16 + 1);
+    }
+
+    #[test]
+    fn transfer_batch_reports_failed_entries() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+        contract.mint_batch(vec![admin.clone(), bob.clone()]);
+
+        // token 2 belongs to bob, token 9 does not exist
+        set_context(admin.clone());
+        let outcome = contract.transfer_batch(vec![
+            (0, bob.clone()),
+            (2, admin),
+            (9, bob.clone()),
+            (1, bob.clone()),
+        ]);
+        assert_eq!(
+            outcome,
+            BatchOutcome {
+                succeeded: vec![0, 1],
+                failed: vec![2, 9],
+                processed: 4,
+            }
+        );
+        assert_eq!(contract.owner_of(0).unwrap(), bob);
+        assert_eq!(contract.owner_of(1).unwrap(), bob);
+    }
+
+    #[test]
+    fn migrate_reads_v0_layout() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        {
+            let mut tokens = LookupMap::new(b"tokens".to_vec());
+            tokens.insert(0, admin.clone());
+            tokens.insert(1, "bob.near".parse().unwrap());
+            env::state_write(&ContractV0 {
+                tokens,
+                approvals: LookupMap::new(b"approvals".to_vec()),
+                supply: 2,
+            });
+        }
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.owner_of(0).unwrap(), admin);
+        assert_eq!(contract.owner_of(1).unwrap().as_str(), "bob.near");
+        assert_eq!(contract.supply, 2);
+        assert_eq!(contract.supply_info().minted, 2.into());
+        let self_account = env::current_account_id();
+        assert_eq!(contract.contract_owner(), self_account);
+        assert!(contract.has_role(Role::Admin, self_account));
+
+        // written back tagged, so the next migrate matches on the version
+        assert_eq!(contract.version, STATE_VERSION);
+        env::state_write(&contract);
+        assert_eq!(env::storage_read(b"STATE").unwrap()[0], STATE_VERSION);
+    }
+
+    #[test]
+    fn migrate_keeps_current_layout() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        set_context(admin.clone());
+        let mut contract = Contract::init(admin.clone(), None);
+        contract.mint(None, None).unwrap();
+        env::state_write(&contract);
+
+        let migrated = Contract::migrate();
+        assert_eq!(migrated.contract_owner(), admin);
+        assert_eq!(migrated.supply, 2);
+        assert!(migrated.has_role(Role::Minter, admin));
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown state version 9!")]
+    fn migrate_rejects_unknown_version() {
+        set_context("admin.near".parse().unwrap());
+        let mut contract = Contract::init("admin.near".parse().unwrap(), None);
+        contract.version = 9;
+        env::state_write(&contract);
+        Contract::migrate();
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role!")]
+    fn update_contract_requires_admin() {
+        let contract = Contract::init("admin.near".parse().unwrap(), None);
+        let mut builder = VMContextBuilder::new();
+        builder.predecessor_account_id("bob.near".parse().unwrap());
+        builder.context.input = b"\0asm".as_slice().into();
+        testing_env!(builder.build());
+        contract.update_contract().detach();
+    }
+
+    #[test]
+    fn expired_approval_cannot_transfer() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin.clone());
+        let expiry = Expiry::BlockHeight(10.into());
+        contract.approve(0, bob.clone(), Some(expiry)).unwrap();
+        assert_eq!(contract.approval_expiry(0), Some(expiry));
+
+        // still valid before the expiry height
+        set_context_at(bob.clone(), 9);
+        assert_eq!(contract.check_owner_or_approved(0), Ok(admin.clone()));
+
+        set_context_at(bob.clone(), 10);
+        assert_eq!(
+            contract.transfer(0, bob),
+            Err(ContractError::ApprovalExpired)
+        );
+        assert_eq!(contract.owner_of(0).unwrap(), admin);
+    }
+
+    #[test]
+    fn prune_expired_approvals_removes_only_expired() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin.clone());
+        contract.mint_batch(vec![admin.clone(), admin.clone()]);
+        contract
+            .approve(0, bob.clone(), Some(Expiry::Timestamp(1_000.into())))
+            .unwrap();
+        contract
+            .approve(1, bob.clone(), Some(Expiry::Timestamp(5_000.into())))
+            .unwrap();
+        contract.approve(2, bob.clone(), None).unwrap();
+
+        let mut builder = VMContextBuilder::new();
+        builder.predecessor_account_id(bob).block_timestamp(2_000);
+        testing_env!(builder.build());
+        assert_eq!(contract.prune_expired_approvals(), 1);
+        assert!(contract.approvals.get(&0).is_none());
+        assert!(contract.approvals.get(&1).is_some());
+        assert!(contract.approvals.get(&2).is_some());
+        assert_eq!(contract.approval_expiry(0), None);
+    }
+
+    #[test]
+    fn transfer_call_moves_token_before_callback() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let receiver: AccountId = "receiver.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin);
+        contract
+            .transfer_call(0, receiver.clone(), "keep".to_string())
+            .unwrap()
+            .detach();
+        assert_eq!(contract.owner_of(0).unwrap(), receiver);
+    }
+
+    #[test]
+    fn resolve_transfer_reverts_when_receiver_returns_token() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let receiver: AccountId = "receiver.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin.clone());
+        contract.transfer(0, receiver.clone()).unwrap();
+
+        assert!(!contract.internal_resolve_transfer(
+            admin.clone(),
+            receiver.clone(),
+            0,
+            Ok(b"true".to_vec())
+        ));
+        assert_eq!(contract.owner_of(0).unwrap(), admin);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"old_owner_id":"receiver.near","new_owner_id":"admin.near","token_ids":["0"]}]}"#
+        );
+    }
+
+    #[test]
+    fn resolve_transfer_reverts_when_receiver_fails() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let receiver: AccountId = "receiver.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin.clone());
+        contract.transfer(0, receiver.clone()).unwrap();
+
+        assert!(!contract.internal_resolve_transfer(
+            admin.clone(),
+            receiver,
+            0,
+            Err(near_sdk::PromiseError::Failed)
+        ));
+        assert_eq!(contract.owner_of(0).unwrap(), admin);
+    }
+
+    #[test]
+    fn resolve_transfer_reverts_when_receiver_returns_garbage() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let receiver: AccountId = "receiver.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin.clone());
+
+        for garbage in [&b"\"false\""[..], b"0", b"not json", b""] {
+            contract.transfer(0, receiver.clone()).unwrap();
+            assert!(!contract.internal_resolve_transfer(
+                admin.clone(),
+                receiver.clone(),
+                0,
+                Ok(garbage.to_vec())
+            ));
+            assert_eq!(contract.owner_of(0).unwrap(), admin);
+        }
+    }
+
+    #[test]
+    fn resolve_transfer_keeps_token() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let receiver: AccountId = "receiver.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin.clone());
+        contract.transfer(0, receiver.clone()).unwrap();
+        assert!(contract.internal_resolve_transfer(
+            admin.clone(),
+            receiver.clone(),
+            0,
+            Ok(b"false".to_vec())
+        ));
+        assert_eq!(contract.owner_of(0).unwrap(), receiver);
+
+        // already moved on by the receiver, nothing to revert
+        contract.tokens.insert(0, "carol.near".parse().unwrap());
+        assert!(contract.internal_resolve_transfer(admin, receiver, 0, Ok(b"true".to_vec())));
+        assert_eq!(contract.owner_of(0).unwrap().as_str(), "carol.near");
+    }
+
+    #[test]
+    fn soulbound_collection_rejects_approve_and_transfer() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), Some(false));
+        set_context(admin.clone());
+        assert!(!contract.is_transferable(0));
+        assert_eq!(
+            contract.approve(0, bob.clone(), None),
+            Err(ContractError::NonTransferable)
+        );
+        assert_eq!(
+            contract.transfer(0, bob.clone()),
+            Err(ContractError::NonTransferable)
+        );
+        let outcome = contract.transfer_batch(vec![(0, bob)]);
+        assert_eq!(outcome.failed, vec![0]);
+        assert_eq!(contract.owner_of(0).unwrap(), admin);
+    }
+
+    #[test]
+    fn soulbound_token_minted_in_transferable_collection() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin.clone());
+        let badge = contract.mint(None, Some(false)).unwrap();
+        let regular = contract.mint(None, None).unwrap();
+        assert!(!contract.is_transferable(badge));
+        assert!(contract.is_transferable(regular));
+        assert_eq!(
+            contract.transfer(badge, bob.clone()),
+            Err(ContractError::NonTransferable)
+        );
+        contract.transfer(regular, bob).unwrap();
+    }
+
+    #[test]
+    fn admin_recovers_soulbound_token() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), Some(false));
+        set_context(admin.clone());
+        contract.mint_batch(vec![bob.clone()]);
+
+        set_context(admin);
+        contract
+            .recover(1, "bob-new.near".parse().unwrap())
+            .unwrap();
+        assert_eq!(contract.owner_of(1).unwrap().as_str(), "bob-new.near");
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"authorized_id":"admin.near","old_owner_id":"bob.near","new_owner_id":"bob-new.near","token_ids":["1"]}]}"#,
+            ]
+        );
+
+        // the holder cannot recover it themselves
+        set_context(bob);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.recover(1, "bob.near".parse().unwrap())
+        }));
+        assert!(result.is_err());
+    }
+
+    // allowlist 100..200, public 200..300, 1 NEAR per token, 2 per account
+    fn sale_fixture(allowlisted: &[AccountId]) -> (Contract, Vec<Vec<Base58CryptoHash>>) {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        let leaves: Vec<CryptoHash> = allowlisted
+            .iter()
+            .map(|a| env::sha256_array(a.as_bytes()))
+            .collect();
+        // two leaves: the root is their pair hash, each proof is the other leaf
+        let root = sale::hash_pair(&leaves[0], &leaves[1]);
+        let proofs = vec![vec![leaves[1].into()], vec![leaves[0].into()]];
+        set_context(admin);
+        contract.configure_sale(SaleConfig {
+            price: U128(NearToken::from_near(1).as_yoctonear()),
+            allowlist_start: 100.into(),
+            public_start: 200.into(),
+            end: 300.into(),
+            max_per_account: 2,
+            merkle_root: Some(root.into()),
+        });
+        (contract, proofs)
+    }
+
+    #[test]
+    fn sale_phases_follow_schedule() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let dave: AccountId = "dave.near".parse().unwrap();
+        let (mut contract, proofs) = sale_fixture(&[bob.clone(), carol]);
+
+        set_buy_context(bob.clone(), 50, NearToken::from_near(1));
+        assert_eq!(contract.sale_phase(), SalePhase::NotStarted);
+        assert_eq!(contract.buy(None), Err(ContractError::SaleNotActive));
+
+        // allowlist phase: proof required, and it must be the caller's
+        set_buy_context(bob.clone(), 150, NearToken::from_near(1));
+        assert_eq!(contract.sale_phase(), SalePhase::Allowlist);
+        assert_eq!(contract.buy(None), Err(ContractError::NotAllowlisted));
+        assert_eq!(
+            contract.buy(Some(proofs[1].clone())),
+            Err(ContractError::NotAllowlisted)
+        );
+        let id = contract.buy(Some(proofs[0].clone())).unwrap();
+        assert_eq!(contract.owner_of(id).unwrap(), bob);
+        set_buy_context(dave.clone(), 150, NearToken::from_near(1));
+        assert_eq!(
+            contract.buy(Some(proofs[0].clone())),
+            Err(ContractError::NotAllowlisted)
+        );
+
+        // public phase: anyone
+        set_buy_context(dave.clone(), 250, NearToken::from_near(1));
+        assert_eq!(contract.sale_phase(), SalePhase::Public);
+        let id = contract.buy(None).unwrap();
+        assert_eq!(contract.owner_of(id).unwrap(), dave);
+
+        set_buy_context(dave, 300, NearToken::from_near(1));
+        assert_eq!(contract.sale_phase(), SalePhase::Ended);
+        assert_eq!(contract.buy(None), Err(ContractError::SaleNotActive));
+        assert_eq!(
+            contract.sale_proceeds(),
+            U128(NearToken::from_near(2).as_yoctonear())
+        );
+    }
+
+    #[test]
+    fn sale_enforces_price_and_account_limit() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let (mut contract, _) = sale_fixture(&[bob.clone(), "carol.near".parse().unwrap()]);
+
+        set_buy_context(bob.clone(), 250, NearToken::from_millinear(999));
+        assert_eq!(contract.buy(None), Err(ContractError::InsufficientDeposit));
+
+        // overpaying is fine, the excess is refunded
+        set_buy_context(bob.clone(), 250, NearToken::from_near(3));
+        contract.buy(None).unwrap();
+        set_buy_context(bob.clone(), 250, NearToken::from_near(1));
+        contract.buy(None).unwrap();
+        assert_eq!(contract.sale_minted(bob.clone()), 2);
+        assert_eq!(contract.buy(None), Err(ContractError::MintLimitReached));
+    }
+
+    #[test]
+    fn admin_withdraws_proceeds() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let (mut contract, _) = sale_fixture(&[bob.clone(), "carol.near".parse().unwrap()]);
+        set_buy_context(bob.clone(), 250, NearToken::from_near(1));
+        contract.buy(None).unwrap();
+
+        set_context(admin);
+        contract.withdraw_proceeds().detach();
+        assert_eq!(contract.sale_proceeds(), U128(0));
+
+        set_context(bob);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.withdraw_proceeds().detach()
+        }));
+        assert!(result.is_err());
+    }
+
+    // Auxiliar fn: create a mock context for a sale purchase
+    fn set_buy_context(buyer: AccountId, timestamp: u64, deposit: NearToken) {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .predecessor_account_id(buyer)
+            .block_timestamp(timestamp)
+            .attached_deposit(deposit);
+
+        testing_env!(builder.build());
+    }
+
+    #[test]
+    fn list_owners_paginates() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin.clone());
+        contract.mint_batch(vec![bob.clone(), admin.clone(), bob.clone()]);
+        contract.burn(2).unwrap();
+
+        assert_eq!(
+            contract.list_owners(None, Some(2)),
+            vec![
+                TokenOwner {
+                    id: 0,
+                    owner: admin.clone()
+                },
+                TokenOwner {
+                    id: 1,
+                    owner: bob.clone()
+                },
+            ]
+        );
+        // the burned id 2 is skipped, the page ends at supply
+        assert_eq!(
+            contract.list_owners(Some(2), Some(10)),
+            vec![TokenOwner { id: 3, owner: bob }]
+        );
+        assert!(contract.list_owners(Some(4), None).is_empty());
+    }
+
+    #[test]
+    fn list_approvals_includes_expiry() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::init(admin.clone(), None);
+        set_context(admin.clone());
+        contract.mint_batch(vec![admin.clone(), admin.clone()]);
+        let expiry = Expiry::BlockHeight(42.into());
+        contract.approve(0, bob.clone(), None).unwrap();
+        contract.approve(2, bob.clone(), Some(expiry)).unwrap();
+
+        assert_eq!(
+            contract.list_approvals(None, None),
+            vec![
+                TokenApproval {
+                    id: 0,
+                    delegatee: bob.clone(),
+                    expiry: None
+                },
+                TokenApproval {
+                    id: 2,
+                    delegatee: bob,
+                    expiry: Some(expiry)
+                },
+            ]
+        );
+        assert_eq!(contract.list_approvals(Some(1), Some(1)), vec![]);
+    }
+
+    // Auxiliar fn: grant the minter role as admin, then switch back to the minter
+    fn grant_minter(contract: &mut Contract, admin: &AccountId, minter: &AccountId) {
+        set_context(admin.clone());
+        contract.grant_role(Role::Minter, minter.clone());
+        set_context(minter.clone());
+    }
+
+    // Auxiliar fn: create a mock context at a given block height
+    fn set_context_at(predecessor: AccountId, block_height: u64) {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .predecessor_account_id(predecessor)
+            .block_height(block_height);
+
+        testing_env!(builder.build());
+    }
+
+    /// Transfers scheduled by the current call, as `(receiver, yoctoNEAR)`.
+    fn transfers() -> Vec<(AccountId, u128)> {
+        get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        MockAction::Transfer { deposit, .. } => {
+                            Some((receiver.clone(), deposit.as_yoctonear()))
+                        }
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
     // Auxiliar fn: create a mock context
     fn set_context(predecessor: AccountId) {
         let mut builder = VMContextBuilder::new();
@@ -0,0 +1,46 @@
+use near_sdk::{near, require};
+
+use crate::roles::Role;
+use crate::{Contract, ContractExt, Id};
+
+#[near]
+impl Contract {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_frozen(&self, id: Id) -> bool {
+        self.frozen.contains(&id)
+    }
+
+    // emergency stop: halts mint, approve and transfer, views keep working
+    pub fn pause(&mut self) {
+        self.assert_role(Role::Pauser);
+        self.paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.assert_role(Role::Pauser);
+        self.paused = false;
+    }
+
+    pub fn freeze(&mut self, id: Id) {
+        self.assert_role(Role::Pauser);
+        self.frozen.insert(id);
+    }
+
+    pub fn unfreeze(&mut self, id: Id) {
+        self.assert_role(Role::Pauser);
+        self.frozen.remove(&id);
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_not_paused(&self) {
+        require!(!self.paused, "contract paused!");
+    }
+
+    pub(crate) fn assert_not_frozen(&self, id: Id) {
+        require!(!self.frozen.contains(&id), "token frozen!");
+    }
+}
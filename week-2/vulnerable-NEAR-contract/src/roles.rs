@@ -0,0 +1,61 @@
+use near_sdk::{env, near, require, AccountId};
+
+use crate::events::RoleEvent;
+use crate::{Contract, ContractExt};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Minter,
+    Pauser,
+    Admin,
+}
+
+#[near]
+impl Contract {
+    pub fn contract_owner(&self) -> AccountId {
+        self.owner.clone()
+    }
+
+    pub fn has_role(&self, role: Role, account: AccountId) -> bool {
+        self.roles.contains(&(role, account))
+    }
+
+    pub fn grant_role(&mut self, role: Role, account: AccountId) {
+        self.assert_role(Role::Admin);
+        if self.roles.insert((role, account.clone())) {
+            RoleEvent::RoleGranted {
+                role,
+                account,
+                sender: env::predecessor_account_id(),
+            }
+            .emit();
+        }
+    }
+
+    pub fn revoke_role(&mut self, role: Role, account: AccountId) {
+        self.assert_role(Role::Admin);
+        // the owner must always be able to manage roles
+        require!(
+            !(role == Role::Admin && account == self.owner),
+            "cannot revoke admin from owner!"
+        );
+        if self.roles.remove(&(role, account.clone())) {
+            RoleEvent::RoleRevoked {
+                role,
+                account,
+                sender: env::predecessor_account_id(),
+            }
+            .emit();
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_role(&self, role: Role) {
+        require!(
+            self.has_role(role, env::predecessor_account_id()),
+            "missing role!"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, near, AccountId};
+
+use crate::{Contract, ContractError, ContractExt, Id};
+
+/// 100% expressed in basis points
+pub const ROYALTY_TOTAL_BPS: u32 = 10_000;
+/// keeps `nft_payout` bounded for marketplaces
+pub const MAX_ROYALTY_ACCOUNTS: usize = 10;
+
+pub type Royalty = HashMap<AccountId, u32>;
+
+#[near(serializers = [json])]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Payout {
+    pub payout: HashMap<AccountId, U128>,
+}
+
+#[near]
+impl Contract {
+    pub fn royalty_of(&self, id: Id) -> Option<Royalty> {
+        self.royalties.get(&id).cloned()
+    }
+
+    // NEP-199
+    #[handle_result]
+    pub fn nft_payout(
+        &self,
+        token_id: String,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Result<Payout, ContractError> {
+        let id = parse_token_id(&token_id)?;
+        let owner = self.tokens.get(&id).ok_or(ContractError::TokenNotFound)?;
+        let royalty = self.royalties.get(&id).cloned().unwrap_or_default();
+
+        // every royalty holder plus the owner gets an entry
+        if let Some(max_len_payout) = max_len_payout {
+            if royalty.len() + 1 > max_len_payout as usize {
+                return Err(ContractError::PayoutTooLong);
+            }
+        }
+
+        let balance = balance.0;
+        let mut payout = HashMap::new();
+        let mut paid = 0;
+        for (account, bps) in royalty {
+            let amount = balance * bps as u128 / ROYALTY_TOTAL_BPS as u128;
+            paid += amount;
+            *payout.entry(account).or_insert(0) += amount;
+        }
+        // the owner receives whatever is left after royalties
+        *payout.entry(owner.clone()).or_insert(0) += balance - paid;
+
+        Ok(Payout {
+            payout: payout.into_iter().map(|(k, v)| (k, U128(v))).collect(),
+        })
+    }
+
+    // NEP-199, `approval_id` and `memo` are accepted for compatibility but unused
+    #[payable]
+    #[handle_result]
+    #[allow(unused_variables)]
+    pub fn nft_transfer_payout(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Result<Payout, ContractError> {
+        assert_one_yocto();
+        let payout = self.nft_payout(token_id.clone(), balance, max_len_payout)?;
+        self.transfer(parse_token_id(&token_id)?, receiver_id)?;
+        Ok(payout)
+    }
+}
+
+pub(crate) fn validate_royalty(royalty: &Royalty) -> Result<(), ContractError> {
+    if royalty.len() > MAX_ROYALTY_ACCOUNTS {
+        return Err(ContractError::TooManyRoyaltyAccounts);
+    }
+    let total = royalty
+        .values()
+        .try_fold(0u32, |acc, bps| acc.checked_add(*bps));
+    match total {
+        Some(total) if total <= ROYALTY_TOTAL_BPS => Ok(()),
+        _ => Err(ContractError::RoyaltyTooHigh),
+    }
+}
+
+fn parse_token_id(token_id: &str) -> Result<Id, ContractError> {
+    token_id.parse().map_err(|_| ContractError::TokenNotFound)
+}
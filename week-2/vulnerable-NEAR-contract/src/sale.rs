@@ -0,0 +1,149 @@
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::{env, near, require, AccountId, CryptoHash, NearToken, Promise};
+
+use crate::roles::Role;
+use crate::{events, Contract, ContractError, ContractExt, Id};
+
+/// Open-edition sale, timestamps are block timestamps in nanoseconds.
+///
+/// `allowlist_start..public_start` only admits accounts in the Merkle tree,
+/// `public_start..end` admits everyone.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaleConfig {
+    /// price per token in yoctoNEAR
+    pub price: U128,
+    pub allowlist_start: U64,
+    pub public_start: U64,
+    pub end: U64,
+    /// tokens one account may buy over the whole sale
+    pub max_per_account: u32,
+    /// root of a sorted-pair sha256 tree over `sha256(account_id)` leaves
+    pub merkle_root: Option<Base58CryptoHash>,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SalePhase {
+    NotConfigured,
+    NotStarted,
+    Allowlist,
+    Public,
+    Ended,
+}
+
+#[near]
+impl Contract {
+    pub fn sale_config(&self) -> Option<SaleConfig> {
+        self.sale.clone()
+    }
+
+    pub fn sale_phase(&self) -> SalePhase {
+        let Some(sale) = &self.sale else {
+            return SalePhase::NotConfigured;
+        };
+        let now = env::block_timestamp();
+        if now < sale.allowlist_start.0 {
+            SalePhase::NotStarted
+        } else if now < sale.public_start.0 {
+            SalePhase::Allowlist
+        } else if now < sale.end.0 {
+            SalePhase::Public
+        } else {
+            SalePhase::Ended
+        }
+    }
+
+    pub fn sale_minted(&self, account: AccountId) -> u32 {
+        self.sale_minted.get(&account).copied().unwrap_or(0)
+    }
+
+    pub fn sale_proceeds(&self) -> U128 {
+        self.proceeds.into()
+    }
+
+    pub fn configure_sale(&mut self, config: SaleConfig) {
+        self.assert_role(Role::Admin);
+        require!(
+            config.allowlist_start.0 <= config.public_start.0
+                && config.public_start.0 <= config.end.0,
+            "invalid sale schedule!"
+        );
+        self.sale = Some(config);
+    }
+
+    /// Buys one token for the caller. Whatever is attached above the price first pays the
+    /// token's storage, refunded on `burn`, and the rest is returned right away.
+    /// `proof` is only needed during the allowlist phase.
+    #[payable]
+    #[handle_result]
+    pub fn buy(&mut self, proof: Option<Vec<Base58CryptoHash>>) -> Result<Id, ContractError> {
+        self.assert_not_paused();
+        let sale = self.sale.clone().ok_or(ContractError::SaleNotActive)?;
+        let buyer = env::predecessor_account_id();
+        match self.sale_phase() {
+            SalePhase::Allowlist => {
+                let root = sale.merkle_root.ok_or(ContractError::NotAllowlisted)?;
+                if !verify_allowlist(&buyer, &proof.unwrap_or_default(), &root.into()) {
+                    return Err(ContractError::NotAllowlisted);
+                }
+            }
+            SalePhase::Public => {}
+            _ => return Err(ContractError::SaleNotActive),
+        }
+
+        let bought = self.sale_minted(buyer.clone());
+        if bought >= sale.max_per_account {
+            return Err(ContractError::MintLimitReached);
+        }
+        let deposit = env::attached_deposit().as_yoctonear();
+        if deposit < sale.price.0 {
+            return Err(ContractError::InsufficientDeposit);
+        }
+
+        self.sale_minted.insert(buyer.clone(), bought + 1);
+        self.proceeds += sale.price.0;
+        let (id, refund) = self.internal_mint_paid(
+            buyer.clone(),
+            None,
+            false,
+            buyer.clone(),
+            deposit - sale.price.0,
+        );
+        events::nft_mint(buyer.clone(), id);
+
+        if refund > 0 {
+            Promise::new(buyer)
+                .transfer(NearToken::from_yoctonear(refund))
+                .detach();
+        }
+        Ok(id)
+    }
+
+    /// Sends every collected sale payment to the calling admin.
+    pub fn withdraw_proceeds(&mut self) -> Promise {
+        self.assert_role(Role::Admin);
+        let amount = self.proceeds;
+        require!(amount > 0, "no proceeds!");
+        self.proceeds = 0;
+        Promise::new(env::predecessor_account_id()).transfer(NearToken::from_yoctonear(amount))
+    }
+}
+
+/// Walks `proof` from the account's leaf up to `root`, hashing sorted pairs.
+pub(crate) fn verify_allowlist(
+    account: &AccountId,
+    proof: &[Base58CryptoHash],
+    root: &CryptoHash,
+) -> bool {
+    let mut node = env::sha256_array(account.as_bytes());
+    for sibling in proof {
+        node = hash_pair(&node, sibling.as_ref());
+    }
+    &node == root
+}
+
+pub(crate) fn hash_pair(a: &CryptoHash, b: &CryptoHash) -> CryptoHash {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    env::sha256_array([left.as_slice(), right.as_slice()].concat())
+}
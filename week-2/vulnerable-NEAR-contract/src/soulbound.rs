@@ -0,0 +1,43 @@
+use near_sdk::{env, near, AccountId};
+
+use crate::roles::Role;
+use crate::{events, Contract, ContractError, ContractExt, Id};
+
+#[near]
+impl Contract {
+    /// `false` for soulbound tokens, either collection-wide or minted as such.
+    pub fn is_transferable(&self, id: Id) -> bool {
+        self.transferable && !self.non_transferable.contains(&id)
+    }
+
+    /// Admin-only recovery of a token to a new account, e.g. a lost key for a badge.
+    /// Works for soulbound tokens and drops any approval.
+    #[handle_result]
+    pub fn recover(&mut self, id: Id, new_owner: AccountId) -> Result<(), ContractError> {
+        self.assert_role(Role::Admin);
+        let old_owner = self
+            .tokens
+            .get(&id)
+            .cloned()
+            .ok_or(ContractError::TokenNotFound)?;
+        self.tokens.insert(id, new_owner.clone());
+        self.approvals.remove(&id);
+        self.approval_expiries.remove(&id);
+        events::nft_transfer(
+            Some(env::predecessor_account_id()),
+            old_owner,
+            new_owner,
+            id,
+        );
+        Ok(())
+    }
+}
+
+impl Contract {
+    pub(crate) fn check_transferable(&self, id: Id) -> Result<(), ContractError> {
+        if !self.is_transferable(id) {
+            return Err(ContractError::NonTransferable);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use near_sdk::json_types::U64;
+use near_sdk::{env, near, AccountId, NearToken, Promise};
+
+use crate::{events, Contract, ContractError, ContractExt, Id, Royalty};
+
+/// Storage payment held for a token until it is burned.
+#[near(serializers = [borsh])]
+pub struct StorageDeposit {
+    pub payer: AccountId,
+    /// in yoctoNEAR, never more than the token's storage cost at mint time
+    pub amount: u128,
+}
+
+#[near(serializers = [json])]
+#[derive(Debug, PartialEq, Eq)]
+pub struct SupplyInfo {
+    pub minted: U64,
+    pub burned: U64,
+    pub circulating: U64,
+    /// id that the next `mint` will allocate
+    pub next_id: u16,
+}
+
+#[near]
+impl Contract {
+    pub fn supply_info(&self) -> SupplyInfo {
+        SupplyInfo {
+            minted: self.minted.into(),
+            burned: self.burned.into(),
+            circulating: self.circulating().into(),
+            next_id: self.supply,
+        }
+    }
+
+    /// Destroys a token and refunds its storage deposit to whoever paid it.
+    #[handle_result]
+    pub fn burn(&mut self, id: Id) -> Result<(), ContractError> {
+        self.assert_not_paused();
+        self.assert_not_frozen(id);
+        let owner = self.check_owner_or_approved(id)?;
+        let sender = env::predecessor_account_id();
+
+        self.tokens.remove(&id);
+        self.approvals.remove(&id);
+        self.approval_expiries.remove(&id);
+        self.royalties.remove(&id);
+        let deposit = self.storage_deposits.remove(&id);
+        self.burned += 1;
+
+        let authorized_id = (sender != owner).then_some(sender);
+        events::nft_burn(authorized_id, owner, id);
+        // tokens minted without a deposit were paid for by the contract, nothing to return
+        if let Some(deposit) = deposit {
+            Promise::new(deposit.payer)
+                .transfer(NearToken::from_yoctonear(deposit.amount))
+                .detach();
+        }
+        Ok(())
+    }
+}
+
+impl Contract {
+    /// Mints like [`Contract::internal_mint`] and keeps up to the new token's storage cost
+    /// out of `attached` as a deposit, refunded to `payer` when the token is burned.
+    /// Returns the id and the part of `attached` that was not kept.
+    pub(crate) fn internal_mint_paid(
+        &mut self,
+        owner: AccountId,
+        royalty: Option<Royalty>,
+        soulbound: bool,
+        payer: AccountId,
+        attached: u128,
+    ) -> (Id, u128) {
+        let storage_before = env::storage_usage();
+        let id = self.internal_mint(owner, royalty, soulbound);
+        // the record is charged too, its size doesn't depend on `amount` so a
+        // placeholder measures the same
+        self.storage_deposits
+            .insert(id, StorageDeposit { payer, amount: 0 });
+        // maps cache writes, flush so the used bytes are measurable
+        self.tokens.flush();
+        self.royalties.flush();
+        self.storage_deposits.flush();
+        let used = env::storage_usage().saturating_sub(storage_before);
+        let amount = env::storage_byte_cost()
+            .as_yoctonear()
+            .saturating_mul(used.into())
+            .min(attached);
+        if amount > 0 {
+            if let Some(deposit) = self.storage_deposits.get_mut(&id) {
+                deposit.amount = amount;
+            }
+        } else {
+            self.storage_deposits.remove(&id);
+        }
+        (id, attached - amount)
+    }
+
+    pub(crate) fn circulating(&self) -> u64 {
+        self.minted - self.burned
+    }
+}
@@ -0,0 +1,101 @@
+use near_sdk::{
+    env, ext_contract, near, require, serde_json, AccountId, Gas, Promise, PromiseError,
+    PromiseOrValue,
+};
+
+use crate::{events, Contract, ContractError, ContractExt, Id};
+
+/// gas reserved for `resolve_transfer` once the receiver returns
+const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas::from_tgas(10);
+/// minimum gas the receiver's `nft_on_transfer` gets to run
+const GAS_FOR_NFT_ON_TRANSFER: Gas = Gas::from_tgas(25);
+/// longest receiver result read back, `false` only needs five bytes
+const MAX_RECEIVER_RESULT_LEN: usize = 16;
+
+/// NEP-171 receiver interface, returns `true` to hand the token back.
+#[ext_contract(ext_nft_receiver)]
+pub trait NftReceiver {
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: String,
+        msg: String,
+    ) -> PromiseOrValue<bool>;
+}
+
+#[near]
+impl Contract {
+    /// Transfers `id` to a receiver contract and notifies it through `nft_on_transfer`,
+    /// reverting the transfer if the receiver asks for it or fails.
+    #[handle_result]
+    pub fn transfer_call(
+        &mut self,
+        id: Id,
+        receiver: AccountId,
+        msg: String,
+    ) -> Result<Promise, ContractError> {
+        require!(
+            env::prepaid_gas().as_gas()
+                >= env::used_gas().as_gas()
+                    + GAS_FOR_NFT_ON_TRANSFER.as_gas()
+                    + GAS_FOR_RESOLVE_TRANSFER.as_gas(),
+            "not enough gas for transfer_call!"
+        );
+        let previous_owner = self.owner_of(id).ok_or(ContractError::TokenNotFound)?;
+        self.transfer(id, receiver.clone())?;
+
+        Ok(ext_nft_receiver::ext(receiver.clone())
+            .with_static_gas(GAS_FOR_NFT_ON_TRANSFER)
+            .nft_on_transfer(
+                env::predecessor_account_id(),
+                previous_owner.clone(),
+                id.to_string(),
+                msg,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .resolve_transfer(previous_owner, receiver, id),
+            ))
+    }
+
+    /// Returns `true` if the token stayed with the receiver.
+    #[private]
+    pub fn resolve_transfer(
+        &mut self,
+        previous_owner: AccountId,
+        receiver: AccountId,
+        id: Id,
+    ) -> bool {
+        // read the raw result, a typed callback argument would panic on garbage and
+        // leave the token with the receiver
+        let result = env::promise_result_checked(0, MAX_RECEIVER_RESULT_LEN);
+        self.internal_resolve_transfer(previous_owner, receiver, id, result)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_resolve_transfer(
+        &mut self,
+        previous_owner: AccountId,
+        receiver: AccountId,
+        id: Id,
+        result: Result<Vec<u8>, PromiseError>,
+    ) -> bool {
+        // only a plain `false` keeps the token, a receiver that panics or returns
+        // anything else gets it reverted
+        let keep =
+            result.is_ok_and(|data| serde_json::from_slice::<bool>(&data).ok() == Some(false));
+        if keep {
+            return true;
+        }
+        // the receiver may already have moved or burned the token, leave it alone
+        if self.tokens.get(&id) != Some(&receiver) {
+            return true;
+        }
+        self.tokens.insert(id, previous_owner.clone());
+        events::nft_transfer(None, receiver, previous_owner, id);
+        false
+    }
+}
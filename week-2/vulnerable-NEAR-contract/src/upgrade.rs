@@ -0,0 +1,115 @@
+use near_sdk::borsh;
+use near_sdk::store::{LookupMap, LookupSet};
+use near_sdk::{env, near, AccountId, Gas, NearToken, Promise};
+
+use crate::roles::Role;
+use crate::{Contract, ContractExt, Id};
+
+/// gas handed to `migrate` on the freshly deployed code
+const MIGRATE_GAS: Gas = Gas::from_tgas(100);
+
+/// Layout tag written as the first byte of `STATE`, bump it with every layout change.
+pub const STATE_VERSION: u8 = 1;
+
+/// Borsh encoding of the `tokens` map prefix, which `ContractV0` starts with.
+/// A tagged state has the tag byte in front of it instead.
+const UNTAGGED_PREFIX: &[u8] = b"\x06\0\0\0tokens";
+
+/// Original layout, before roles, pause, royalties and supply counters existed.
+/// The only one deployed without a version tag.
+#[near(serializers = [borsh])]
+pub(crate) struct ContractV0 {
+    pub tokens: LookupMap<Id, AccountId>,
+    pub approvals: LookupMap<Id, AccountId>,
+    pub supply: u16,
+}
+
+/// Every state layout this code knows how to read, oldest first.
+pub(crate) enum VersionedContract {
+    V0(ContractV0),
+    V1(Box<Contract>),
+}
+
+impl VersionedContract {
+    /// Decodes the stored state according to its version tag.
+    pub fn read() -> Self {
+        let state = env::storage_read(b"STATE").unwrap_or_else(|| env::panic_str("no state!"));
+        if state.starts_with(UNTAGGED_PREFIX) {
+            return VersionedContract::V0(decode(&state));
+        }
+        match state[0] {
+            STATE_VERSION => VersionedContract::V1(Box::new(decode(&state))),
+            version => env::panic_str(&format!("unknown state version {version}!")),
+        }
+    }
+}
+
+fn decode<T: borsh::BorshDeserialize>(state: &[u8]) -> T {
+    borsh::from_slice(state).unwrap_or_else(|_| env::panic_str("corrupt state!"))
+}
+
+impl From<ContractV0> for Contract {
+    fn from(old: ContractV0) -> Self {
+        // V0 had no admin, hand every role to the contract account itself
+        let admin = env::current_account_id();
+        Self {
+            version: STATE_VERSION,
+            tokens: old.tokens,
+            approvals: old.approvals,
+            supply: old.supply,
+            roles: {
+                let mut r = LookupSet::new(b"roles".to_vec());
+                r.insert((Role::Admin, admin.clone()));
+                r.insert((Role::Minter, admin.clone()));
+                r.insert((Role::Pauser, admin.clone()));
+                r
+            },
+            owner: admin,
+            paused: false,
+            frozen: LookupSet::new(b"frozen".to_vec()),
+            royalties: LookupMap::new(b"royalties".to_vec()),
+            minted: old.supply.into(),
+            burned: 0,
+            approval_expiries: LookupMap::new(b"approval_expiries".to_vec()),
+            transferable: true,
+            non_transferable: LookupSet::new(b"non_transferable".to_vec()),
+            sale: None,
+            sale_minted: LookupMap::new(b"sale_minted".to_vec()),
+            proceeds: 0,
+            storage_deposits: LookupMap::new(b"storage_deposits".to_vec()),
+        }
+    }
+}
+
+impl From<VersionedContract> for Contract {
+    fn from(versioned: VersionedContract) -> Self {
+        match versioned {
+            VersionedContract::V0(old) => old.into(),
+            VersionedContract::V1(contract) => *contract,
+        }
+    }
+}
+
+#[near]
+impl Contract {
+    /// Deploys the wasm passed as raw call input, then runs its `migrate`.
+    pub fn update_contract(&self) -> Promise {
+        self.assert_role(Role::Admin);
+        let code = env::input().unwrap_or_else(|| env::panic_str("no code!"));
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call(
+                "migrate".to_string(),
+                Vec::new(),
+                NearToken::from_yoctonear(0),
+                MIGRATE_GAS,
+            )
+            .as_return()
+    }
+
+    #[init(ignore_state)]
+    #[private] // only callable by the contract's account
+    pub fn migrate() -> Self {
+        VersionedContract::read().into()
+    }
+}
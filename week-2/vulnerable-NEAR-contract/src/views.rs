@@ -0,0 +1,66 @@
+use near_sdk::{near, AccountId};
+
+use crate::{Contract, ContractExt, Expiry, Id};
+
+/// page size used when `limit` is omitted, and the most one page returns
+pub const MAX_PAGE_SIZE: u16 = 50;
+
+#[near(serializers = [json])]
+#[derive(Debug, PartialEq, Eq)]
+pub struct TokenOwner {
+    pub id: Id,
+    pub owner: AccountId,
+}
+
+#[near(serializers = [json])]
+#[derive(Debug, PartialEq, Eq)]
+pub struct TokenApproval {
+    pub id: Id,
+    pub delegatee: AccountId,
+    pub expiry: Option<Expiry>,
+}
+
+#[near]
+impl Contract {
+    /// Owners of ids `from_index..from_index + limit`, burned ids are skipped.
+    pub fn list_owners(&self, from_index: Option<u16>, limit: Option<u16>) -> Vec<TokenOwner> {
+        self.page(from_index, limit)
+            .filter_map(|id| {
+                self.tokens.get(&id).map(|owner| TokenOwner {
+                    id,
+                    owner: owner.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Approvals among ids `from_index..from_index + limit`, unapproved ids are skipped.
+    pub fn list_approvals(
+        &self,
+        from_index: Option<u16>,
+        limit: Option<u16>,
+    ) -> Vec<TokenApproval> {
+        self.page(from_index, limit)
+            .filter_map(|id| {
+                self.approvals.get(&id).map(|delegatee| TokenApproval {
+                    id,
+                    delegatee: delegatee.clone(),
+                    expiry: self.approval_expiry(id),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Ids in the requested window, clamped to allocated ids that fit in an `Id`.
+    fn page(&self, from_index: Option<u16>, limit: Option<u16>) -> impl Iterator<Item = Id> {
+        let start = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE);
+        let end = self
+            .supply
+            .min(Id::MAX as u16 + 1)
+            .min(start.saturating_add(limit));
+        (start..end).map(|id| id as Id)
+    }
+}
@@ -0,0 +1,32 @@
+use near_sdk::serde_json::{json, Value};
+use near_sdk::{env, near, AccountId, PromiseOrValue};
+
+/// Reacts to `nft_on_transfer` according to `msg`:
+/// - "keep": accept the token
+/// - "return": ask the sender to hand the token back
+/// - "garbage": answer with something that is not a bool
+/// - "panic": fail the call
+#[near(contract_state)]
+#[derive(Default)]
+pub struct MockReceiver {}
+
+#[near]
+impl MockReceiver {
+    pub fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: String,
+        msg: String,
+    ) -> PromiseOrValue<Value> {
+        env::log_str(&format!(
+            "received token {token_id} from {previous_owner_id} via {sender_id}"
+        ));
+        match msg.as_str() {
+            "keep" => PromiseOrValue::Value(json!(false)),
+            "return" => PromiseOrValue::Value(json!(true)),
+            "garbage" => PromiseOrValue::Value(json!({ "return": false })),
+            _ => env::panic_str("receiver panicked!"),
+        }
+    }
+}
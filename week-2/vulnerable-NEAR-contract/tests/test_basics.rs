@@ -0,0 +1,217 @@
+use near_workspaces::network::Sandbox;
+use near_workspaces::result::ExecutionFinalResult;
+use near_workspaces::types::Gas;
+use near_workspaces::{Account, Contract, Worker};
+use serde_json::json;
+
+// upper bound for a single mint/approve/transfer call
+const MAX_CALL_GAS: Gas = Gas::from_tgas(10);
+
+#[tokio::test]
+async fn test_contract_is_operational() -> Result<(), Box<dyn std::error::Error>> {
+    let contract_wasm = near_workspaces::compile_project("./").await?;
+
+    test_basics_on(&contract_wasm).await?;
+    test_approval_transfer_exploit_on(&contract_wasm).await?;
+    test_mint_overflow_exploit_on(&contract_wasm).await?;
+
+    let receiver_wasm = near_workspaces::compile_project("./tests/contracts/mock-receiver").await?;
+    test_transfer_call_on(&contract_wasm, &receiver_wasm).await?;
+    Ok(())
+}
+
+async fn test_basics_on(contract_wasm: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let sandbox = near_workspaces::sandbox().await?;
+    let (contract, admin, bob) = setup(&sandbox, contract_wasm).await?;
+
+    // init minted token 0 to the admin
+    assert_eq!(owner_of(&contract, 0).await?, Some(admin.id().to_string()));
+
+    // bob cannot mint without the minter role
+    let outcome = bob
+        .call(contract.id(), "mint")
+        .args_json(json!({}))
+        .transact()
+        .await?;
+    assert_failure(outcome, "missing role!");
+
+    let id = mint(&contract, &bob).await?;
+    assert_eq!(id, 1);
+    assert_eq!(owner_of(&contract, id).await?, Some(bob.id().to_string()));
+
+    // admin cannot move bob's token without an approval
+    let outcome = admin
+        .call(contract.id(), "transfer")
+        .args_json(json!({"id": id, "receiver": admin.id()}))
+        .transact()
+        .await?;
+    assert_failure(outcome, "not owner or approved!");
+
+    let outcome = bob
+        .call(contract.id(), "approve")
+        .args_json(json!({"id": id, "delegatee": admin.id()}))
+        .transact()
+        .await?;
+    assert_success(&outcome);
+
+    let outcome = admin
+        .call(contract.id(), "transfer")
+        .args_json(json!({"id": id, "receiver": admin.id()}))
+        .transact()
+        .await?;
+    assert_success(&outcome);
+    assert_eq!(owner_of(&contract, id).await?, Some(admin.id().to_string()));
+
+    // missing tokens surface a readable error
+    let outcome = admin
+        .call(contract.id(), "transfer")
+        .args_json(json!({"id": 200, "receiver": bob.id()}))
+        .transact()
+        .await?;
+    assert_failure(outcome, "token not found!");
+    Ok(())
+}
+
+async fn test_approval_transfer_exploit_on(
+    contract_wasm: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sandbox = near_workspaces::sandbox().await?;
+    let (contract, admin, bob) = setup(&sandbox, contract_wasm).await?;
+    let id = mint(&contract, &bob).await?;
+
+    // bob approves himself, then sells the token to the admin
+    let outcome = bob
+        .call(contract.id(), "approve")
+        .args_json(json!({"id": id, "delegatee": bob.id()}))
+        .transact()
+        .await?;
+    assert_success(&outcome);
+    let outcome = bob
+        .call(contract.id(), "transfer")
+        .args_json(json!({"id": id, "receiver": admin.id()}))
+        .transact()
+        .await?;
+    assert_success(&outcome);
+    assert_eq!(owner_of(&contract, id).await?, Some(admin.id().to_string()));
+
+    // the stale approval lets bob take the token back
+    let outcome = bob
+        .call(contract.id(), "transfer")
+        .args_json(json!({"id": id, "receiver": bob.id()}))
+        .transact()
+        .await?;
+    assert_success(&outcome);
+    assert_eq!(owner_of(&contract, id).await?, Some(bob.id().to_string()));
+    Ok(())
+}
+
+async fn test_mint_overflow_exploit_on(
+    contract_wasm: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sandbox = near_workspaces::sandbox().await?;
+    let (contract, admin, bob) = setup(&sandbox, contract_wasm).await?;
+    assert_eq!(owner_of(&contract, 0).await?, Some(admin.id().to_string()));
+
+    // the 256th mint wraps the u8 id and overwrites token 0
+    for _ in 0..256 {
+        mint(&contract, &bob).await?;
+    }
+    assert_eq!(owner_of(&contract, 0).await?, Some(bob.id().to_string()));
+    Ok(())
+}
+
+async fn test_transfer_call_on(
+    contract_wasm: &[u8],
+    receiver_wasm: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sandbox = near_workspaces::sandbox().await?;
+    let (contract, _admin, bob) = setup(&sandbox, contract_wasm).await?;
+    let receiver = sandbox.dev_deploy(receiver_wasm).await?;
+
+    // the mock receiver keeps, returns, answers garbage or panics depending on `msg`
+    for (msg, kept) in [
+        ("keep", true),
+        ("return", false),
+        ("garbage", false),
+        ("panic", false),
+    ] {
+        let id = mint(&contract, &bob).await?;
+        let outcome = bob
+            .call(contract.id(), "transfer_call")
+            .args_json(json!({"id": id, "receiver": receiver.id(), "msg": msg}))
+            .max_gas()
+            .transact()
+            .await?;
+        assert!(outcome.is_success(), "{:#?}", outcome.failures());
+        assert_eq!(outcome.json::<bool>()?, kept, "msg: {msg}");
+
+        let expected_owner = if kept { receiver.id() } else { bob.id() };
+        assert_eq!(
+            owner_of(&contract, id).await?,
+            Some(expected_owner.to_string()),
+            "msg: {msg}"
+        );
+    }
+    Ok(())
+}
+
+// Auxiliar fn: deploy and init the contract, create the admin and a minter (bob)
+async fn setup(
+    sandbox: &Worker<Sandbox>,
+    contract_wasm: &[u8],
+) -> Result<(Contract, Account, Account), Box<dyn std::error::Error>> {
+    let contract = sandbox.dev_deploy(contract_wasm).await?;
+    let admin = sandbox.dev_create_account().await?;
+    let bob = sandbox.dev_create_account().await?;
+
+    let outcome = contract
+        .call("init")
+        .args_json(json!({"admin": admin.id()}))
+        .transact()
+        .await?;
+    assert!(outcome.is_success(), "{:#?}", outcome.into_result());
+
+    let outcome = admin
+        .call(contract.id(), "grant_role")
+        .args_json(json!({"role": "Minter", "account": bob.id()}))
+        .transact()
+        .await?;
+    assert_success(&outcome);
+    Ok((contract, admin, bob))
+}
+
+// Auxiliar fn: mint a token as `minter` and return its id
+async fn mint(contract: &Contract, minter: &Account) -> Result<u8, Box<dyn std::error::Error>> {
+    let outcome = minter
+        .call(contract.id(), "mint")
+        .args_json(json!({}))
+        .transact()
+        .await?;
+    assert_success(&outcome);
+    Ok(outcome.json()?)
+}
+
+async fn owner_of(
+    contract: &Contract,
+    id: u8,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    Ok(contract
+        .view("owner_of")
+        .args_json(json!({"id": id}))
+        .await?
+        .json()?)
+}
+
+fn assert_success(outcome: &ExecutionFinalResult) {
+    assert!(outcome.is_success(), "{:#?}", outcome.failures());
+    assert!(
+        outcome.total_gas_burnt < MAX_CALL_GAS,
+        "burnt {} gas",
+        outcome.total_gas_burnt
+    );
+}
+
+fn assert_failure(outcome: ExecutionFinalResult, message: &str) {
+    let err = outcome.into_result().unwrap_err();
+    assert!(format!("{err:?}").contains(message), "{err:#?}");
+}
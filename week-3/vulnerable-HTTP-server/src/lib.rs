@@ -0,0 +1,857 @@
+//! The HTTP server as a library, so binaries and tests can build it in-process.
+
+use axum::{
+    extract::{
+        rejection::{BytesRejection, JsonRejection, QueryRejection},
+        DefaultBodyLimit, Request, State,
+    },
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+mod limits;
+mod rate_limit;
+pub mod storage;
+mod store;
+
+pub use limits::Limits;
+pub use rate_limit::{RateLimiter, RateLimits};
+use storage::{KvStore, MemoryStore, StorageConfig, StorageError};
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Operation {
+    Addition,
+    Subtraction,
+    Multiplication,
+    Division,
+    Modulo,
+    Power,
+    And,
+    Or,
+    Xor,
+}
+
+impl Operation {
+    const ALL: [Operation; 9] = [
+        Operation::Addition,
+        Operation::Subtraction,
+        Operation::Multiplication,
+        Operation::Division,
+        Operation::Modulo,
+        Operation::Power,
+        Operation::And,
+        Operation::Or,
+        Operation::Xor,
+    ];
+
+    fn apply(self, a: u64, b: u64) -> Result<u64, ApiError> {
+        match self {
+            Operation::Addition => a.checked_add(b).ok_or(ApiError::Overflow),
+            Operation::Subtraction => a.checked_sub(b).ok_or(ApiError::Overflow),
+            Operation::Multiplication => a.checked_mul(b).ok_or(ApiError::Overflow),
+            Operation::Division => a.checked_div(b).ok_or(ApiError::DivisionByZero),
+            Operation::Modulo => a.checked_rem(b).ok_or(ApiError::DivisionByZero),
+            Operation::Power => u32::try_from(b)
+                .ok()
+                .and_then(|b| a.checked_pow(b))
+                .ok_or(ApiError::Overflow),
+            Operation::And => Ok(a & b),
+            Operation::Or => Ok(a | b),
+            Operation::Xor => Ok(a ^ b),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MathQuery {
+    a: u64,
+    b: u64,
+    // unknown names are rejected by serde with the list of valid variants
+    operation: Operation,
+}
+
+#[derive(Serialize)]
+struct MathResult {
+    result: u64,
+}
+
+#[derive(Debug)]
+enum ApiError {
+    InvalidJson(JsonRejection),
+    InvalidQuery(QueryRejection),
+    UnsupportedMediaType,
+    /// reading the body failed, or it is larger than `max_body_bytes`
+    Body(BytesRejection),
+    MalformedJson(serde_json::Error),
+    TooDeep {
+        max: usize,
+    },
+    TooManyEntries {
+        max: usize,
+    },
+    StorageFull {
+        max: u64,
+    },
+    RateLimited {
+        retry_after: u64,
+    },
+    DivisionByZero,
+    Overflow,
+    Panic {
+        request_id: u64,
+    },
+    Storage(StorageError),
+    /// rate limiting is on but the app was served without `ConnectInfo<SocketAddr>`
+    NoPeerAddress,
+    InvalidKey,
+    NotFound,
+    /// `If-None-Match: *` on a key that already exists
+    KeyExists,
+    /// `If-Match` names a version that is no longer stored
+    PreconditionFailed,
+    /// the entry changed between reading and writing it
+    Conflict,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidJson(rejection) => rejection.status(),
+            ApiError::InvalidQuery(rejection) => rejection.status(),
+            ApiError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::Body(rejection) => rejection.status(),
+            ApiError::MalformedJson(e) if e.is_data() => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::MalformedJson(_) => StatusCode::BAD_REQUEST,
+            ApiError::TooDeep { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::TooManyEntries { .. } | ApiError::StorageFull { .. } => {
+                StatusCode::INSUFFICIENT_STORAGE
+            }
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::DivisionByZero | ApiError::Overflow => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Panic { .. } | ApiError::Storage(_) | ApiError::NoPeerAddress => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            ApiError::InvalidKey => StatusCode::BAD_REQUEST,
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::KeyExists | ApiError::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            ApiError::Conflict => StatusCode::CONFLICT,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidJson(_) => "invalid_json",
+            ApiError::InvalidQuery(_) => "invalid_query",
+            ApiError::UnsupportedMediaType => "unsupported_media_type",
+            ApiError::Body(rejection) if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+                "body_too_large"
+            }
+            ApiError::Body(_) => "invalid_body",
+            ApiError::MalformedJson(_) => "invalid_json",
+            ApiError::TooDeep { .. } => "json_too_deep",
+            ApiError::TooManyEntries { .. } => "too_many_entries",
+            ApiError::StorageFull { .. } => "storage_full",
+            ApiError::RateLimited { .. } => "rate_limited",
+            ApiError::DivisionByZero => "division_by_zero",
+            ApiError::Overflow => "overflow",
+            ApiError::Panic { .. } => "internal_error",
+            ApiError::Storage(_) => "storage_error",
+            ApiError::NoPeerAddress => "no_peer_address",
+            ApiError::InvalidKey => "invalid_key",
+            ApiError::NotFound => "not_found",
+            ApiError::KeyExists => "key_exists",
+            ApiError::PreconditionFailed => "precondition_failed",
+            ApiError::Conflict => "conflict",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            ApiError::InvalidJson(rejection) => rejection.body_text(),
+            ApiError::InvalidQuery(rejection) => rejection.body_text(),
+            ApiError::UnsupportedMediaType => {
+                "expected request with `Content-Type: application/json`".to_string()
+            }
+            ApiError::Body(rejection) => rejection.body_text(),
+            ApiError::MalformedJson(e) => e.to_string(),
+            ApiError::TooDeep { max } => format!("JSON nests deeper than {max} levels"),
+            ApiError::TooManyEntries { max } => format!("store is limited to {max} entries"),
+            ApiError::StorageFull { max } => format!("store is limited to {max} bytes"),
+            ApiError::RateLimited { retry_after } => {
+                format!("too many requests, retry in {retry_after}s")
+            }
+            ApiError::DivisionByZero => "b must not be zero for this operation".to_string(),
+            ApiError::Overflow => "result does not fit in a u64".to_string(),
+            ApiError::Panic { request_id } => {
+                format!("internal error, see server logs for request {request_id}")
+            }
+            ApiError::Storage(e) => e.to_string(),
+            ApiError::NoPeerAddress => "the server cannot tell clients apart to rate limit them, \
+                 it must be served with `into_make_service_with_connect_info::<SocketAddr>()`"
+                .to_string(),
+            ApiError::InvalidKey => format!(
+                "keys must be 1 to {} bytes and not `{}`",
+                store::MAX_KEY_LEN,
+                store::RESERVED_KEY
+            ),
+            ApiError::NotFound => "no entry with this key".to_string(),
+            ApiError::KeyExists => "key already exists".to_string(),
+            ApiError::PreconditionFailed => "entry does not match If-Match".to_string(),
+            ApiError::Conflict => "entry was modified concurrently, retry".to_string(),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::InvalidJson(rejection)
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::InvalidQuery(rejection)
+    }
+}
+
+impl From<BytesRejection> for ApiError {
+    fn from(rejection: BytesRejection) -> Self {
+        ApiError::Body(rejection)
+    }
+}
+
+impl From<StorageError> for ApiError {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::TooManyEntries { max } => ApiError::TooManyEntries { max },
+            StorageError::Full { max } => ApiError::StorageFull { max },
+            e => ApiError::Storage(e),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.message(),
+        };
+        let mut response = (self.status(), Json(body)).into_response();
+        if let ApiError::RateLimited { retry_after } = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
+    }
+}
+
+tokio::task_local! {
+    // lets the panic hook tell which request was being served
+    static REQUEST_ID: u64;
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Turns a panicking handler into a 500 for that request only, instead of
+/// taking the connection (or, with an aborting hook, the process) down.
+async fn catch_panic(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+    state.metrics.requests.fetch_add(1, Ordering::Relaxed);
+    let result = REQUEST_ID
+        .scope(
+            request_id,
+            AssertUnwindSafe(next.run(request)).catch_unwind(),
+        )
+        .await;
+
+    let mut response = match result {
+        Ok(response) => response,
+        Err(_) => {
+            state.metrics.panics.fetch_add(1, Ordering::Relaxed);
+            ApiError::Panic { request_id }.into_response()
+        }
+    };
+    response
+        .headers_mut()
+        .insert("x-request-id", HeaderValue::from(request_id));
+    response
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Logs every panic with its request id and location, aborting afterwards
+/// only when `abort_on_panic` is set.
+pub fn install_panic_hook(abort_on_panic: bool) {
+    panic::set_hook(Box::new(move |info| {
+        let request_id = REQUEST_ID
+            .try_with(|id| id.to_string())
+            .unwrap_or_else(|_| "-".to_string());
+        let location = info
+            .location()
+            .map(|location| location.to_string())
+            .unwrap_or_else(|| "unknown location".to_string());
+        eprintln!(
+            "request {request_id} panicked at {location}: {}",
+            panic_message(info.payload())
+        );
+        if abort_on_panic {
+            eprintln!("--abort-on-panic is set, aborting the process...");
+            process::abort();
+        }
+    }));
+}
+
+/// Startup options, see `Config::from_args`.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// abort the whole process on a handler panic instead of failing the request
+    pub abort_on_panic: bool,
+    pub storage: StorageConfig,
+    pub limits: Limits,
+    pub rate_limits: RateLimits,
+}
+
+impl Config {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--abort-on-panic" => config.abort_on_panic = true,
+                "--storage" => {
+                    let value = args.next().ok_or("--storage needs a value")?;
+                    config.storage = value.parse()?;
+                }
+                "--max-body-bytes" => config.limits.max_body_bytes = number(&arg, args.next())?,
+                "--max-json-depth" => config.limits.max_json_depth = number(&arg, args.next())?,
+                "--max-entries" => config.limits.max_entries = number(&arg, args.next())?,
+                "--max-total-bytes" => config.limits.max_total_bytes = number(&arg, args.next())?,
+                "--math-rate-limit" => {
+                    config.rate_limits.math_per_minute = number(&arg, args.next())?
+                }
+                "--store-rate-limit" => {
+                    config.rate_limits.store_per_minute = number(&arg, args.next())?
+                }
+                "--api-key" => {
+                    let key = args.next().ok_or("--api-key needs a value")?;
+                    if key.is_empty() || key.len() > rate_limit::MAX_API_KEY_LEN {
+                        return Err(format!(
+                            "--api-key must be 1 to {} bytes",
+                            rate_limit::MAX_API_KEY_LEN
+                        ));
+                    }
+                    config.rate_limits.api_keys.insert(key);
+                }
+                _ => return Err(format!("unknown argument `{arg}`")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    value
+        .as_deref()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{flag} needs a number"))
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub requests: AtomicU64,
+    pub panics: AtomicU64,
+}
+
+#[derive(Serialize)]
+struct MetricsSnapshot {
+    requests: u64,
+    panics: u64,
+}
+
+/// Everything handlers share, cloned into each request by axum.
+#[derive(Clone)]
+pub struct AppState {
+    pub storage: Arc<dyn KvStore>,
+    pub config: Arc<Config>,
+    pub metrics: Arc<Metrics>,
+    pub rate_limiter: Arc<RateLimiter>,
+}
+
+impl AppState {
+    /// Opens the storage backend named in `config`.
+    pub fn open(config: Config) -> Result<Self, StorageError> {
+        Ok(AppState {
+            storage: storage::open(&config.storage, config.limits.quota())?,
+            config: Arc::new(config),
+            metrics: Arc::default(),
+            rate_limiter: Arc::default(),
+        })
+    }
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        AppState {
+            storage: Arc::new(MemoryStore::default().with_quota(Limits::default().quota())),
+            config: Arc::default(),
+            metrics: Arc::default(),
+            rate_limiter: Arc::default(),
+        }
+    }
+}
+
+async fn calculate(
+    payload: Result<Json<MathQuery>, JsonRejection>,
+) -> Result<Json<MathResult>, ApiError> {
+    let Json(payload) = payload?;
+    let result = payload.operation.apply(payload.a, payload.b)?;
+
+    Ok(Json(MathResult { result }))
+}
+
+async fn list_operations() -> Json<[Operation; 9]> {
+    Json(Operation::ALL)
+}
+
+async fn metrics(State(state): State<AppState>) -> Json<MetricsSnapshot> {
+    Json(MetricsSnapshot {
+        requests: state.metrics.requests.load(Ordering::Relaxed),
+        panics: state.metrics.panics.load(Ordering::Relaxed),
+    })
+}
+
+/// Builds the router with every route and middleware.
+///
+/// Rate limiting keys clients by their address, so the router must be served with
+/// `into_make_service_with_connect_info::<SocketAddr>()`. Otherwise every limited
+/// request fails with `500 no_peer_address`, unless both limits are set to `0`.
+pub fn build_app(state: AppState) -> Router {
+    Router::new()
+        .route("/math", post(calculate))
+        .route("/math/operations", get(list_operations))
+        .merge(store::routes())
+        .route("/metrics", get(metrics))
+        .layer(DefaultBodyLimit::max(state.config.limits.max_body_bytes))
+        // limited requests are turned away before their body is read
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit::rate_limit,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), catch_panic))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::extract::ConnectInfo;
+    use axum::http::Method;
+    use serde_json::{json, Value};
+    use std::collections::HashSet;
+    use std::net::SocketAddr;
+    use tower::ServiceExt;
+
+    /// A request from a fixed peer, as `into_make_service_with_connect_info` would tag it.
+    fn client_request() -> axum::http::request::Builder {
+        Request::builder().extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))))
+    }
+
+    async fn send(
+        app: Router,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = client_request().method(method).uri(uri);
+        let body = match body {
+            Some(body) => {
+                request = request.header("content-type", "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = app.oneshot(request.body(body).unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    #[tokio::test]
+    async fn math_errors_are_typed() {
+        let app = build_app(AppState::default());
+        let (status, body) = send(
+            app.clone(),
+            Method::POST,
+            "/math",
+            Some(json!({"a": 10, "b": 0, "operation": "division"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "division_by_zero");
+
+        let (status, body) = send(
+            app,
+            Method::POST,
+            "/math",
+            Some(json!({"a": u64::MAX, "b": 1, "operation": "addition"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "overflow");
+    }
+
+    #[tokio::test]
+    async fn unknown_operation_lists_valid_ones() {
+        let app = build_app(AppState::default());
+        let (status, body) = send(
+            app.clone(),
+            Method::POST,
+            "/math",
+            Some(json!({"a": 1, "b": 1, "operation": "sqrt"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().contains("`xor`"));
+
+        let (status, body) = send(app, Method::GET, "/math/operations", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), Operation::ALL.len());
+    }
+
+    #[tokio::test]
+    async fn panics_fail_only_their_request() {
+        let state = AppState::default();
+        let app = Router::new()
+            .route("/boom", get(|| async { panic!("boom") as &str }))
+            .layer(middleware::from_fn_with_state(state.clone(), catch_panic))
+            .with_state(state.clone());
+
+        let (status, body) = send(app.clone(), Method::GET, "/boom", None).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "internal_error");
+        let (status, _) = send(app, Method::GET, "/boom", None).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(state.metrics.panics.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn store_shares_state_between_requests() {
+        let app = build_app(AppState::default());
+        let (status, _) = send(app.clone(), Method::POST, "/store", Some(json!({"x": 1}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _) = send(app.clone(), Method::POST, "/store", Some(json!({"x": 2}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, body) = send(app, Method::GET, "/store/all", None).await;
+        // UUIDv7 keys list in insertion order
+        let values: Vec<_> = body["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["value"].clone())
+            .collect();
+        assert_eq!(values, vec![json!({"x": 1}), json!({"x": 2})]);
+    }
+
+    #[tokio::test]
+    async fn put_with_if_none_match_only_creates() {
+        let app = build_app(AppState::default());
+        let put = |value: Value, create_only: bool| {
+            let mut request = client_request()
+                .method(Method::PUT)
+                .uri("/store/my%20key")
+                .header("content-type", "application/json");
+            if create_only {
+                request = request.header("if-none-match", "*");
+            }
+            app.clone()
+                .oneshot(request.body(Body::from(value.to_string())).unwrap())
+        };
+
+        let response = put(json!(1), true).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()["location"], "/store/my%20key");
+        let response = put(json!(2), true).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        let response = put(json!(3), false).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let (_, body) = send(app, Method::GET, "/store/all", None).await;
+        assert_eq!(
+            body,
+            json!({"entries": [{"key": "my key", "value": 3}], "next_cursor": null})
+        );
+    }
+
+    #[tokio::test]
+    async fn crud_with_etags() {
+        let app = build_app(AppState::default());
+        let request = |method: Method, if_match: Option<&str>, body: Option<Value>| {
+            let mut request = client_request().method(method).uri("/store/doc");
+            if let Some(if_match) = if_match {
+                request = request.header("if-match", if_match);
+            }
+            let body = match body {
+                Some(body) => {
+                    request = request.header("content-type", "application/merge-patch+json");
+                    Body::from(body.to_string())
+                }
+                None => Body::empty(),
+            };
+            app.clone().oneshot(request.body(body).unwrap())
+        };
+
+        let response = request(Method::GET, None, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = request(Method::PUT, None, Some(json!({"a": 1, "b": 2})))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let first = response.headers()["etag"].to_str().unwrap().to_string();
+
+        let response = request(Method::HEAD, None, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["etag"], first.as_str());
+
+        let response = request(
+            Method::PATCH,
+            Some(&first),
+            Some(json!({"b": null, "c": 3})),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let second = response.headers()["etag"].to_str().unwrap().to_string();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&bytes).unwrap(),
+            json!({"a": 1, "c": 3})
+        );
+
+        // a writer holding the old version is turned away
+        let response = request(Method::PUT, Some(&first), Some(json!({})))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        let response = request(Method::DELETE, Some(&first), None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+        let response = request(Method::DELETE, Some(&second), None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = request(Method::DELETE, None, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = request(Method::PATCH, None, Some(json!({}))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn listing_pages_filters_and_streams() {
+        let app = build_app(AppState::default());
+        for key in ["a1", "a2", "a3", "b1", "a4"] {
+            let uri = format!("/store/{key}");
+            let (status, _) = send(app.clone(), Method::PUT, &uri, Some(json!(key))).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let mut keys = Vec::new();
+        let mut uri = "/store/all?prefix=a&limit=3".to_string();
+        loop {
+            let (status, body) = send(app.clone(), Method::GET, &uri, None).await;
+            assert_eq!(status, StatusCode::OK);
+            for entry in body["entries"].as_array().unwrap() {
+                keys.push(entry["key"].as_str().unwrap().to_string());
+            }
+            match body["next_cursor"].as_str() {
+                Some(cursor) => uri = format!("/store/all?prefix=a&limit=3&cursor={cursor}"),
+                None => break,
+            }
+        }
+        assert_eq!(keys, ["a1", "a2", "a3", "a4"]);
+
+        let request = client_request()
+            .uri("/store/all?format=ndjson&cursor=a1&limit=2")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            std::str::from_utf8(&bytes).unwrap(),
+            "{\"key\":\"a2\",\"value\":\"a2\"}\n{\"key\":\"a3\",\"value\":\"a3\"}\n"
+        );
+
+        let (status, body) = send(app, Method::GET, "/store/all?limit=many", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_query");
+    }
+
+    #[tokio::test]
+    async fn store_limits_are_enforced() {
+        let config = Config {
+            limits: Limits {
+                max_body_bytes: 64,
+                max_json_depth: 2,
+                max_entries: 2,
+                max_total_bytes: 20,
+            },
+            ..Config::default()
+        };
+        let app = build_app(AppState {
+            storage: Arc::new(MemoryStore::default().with_quota(config.limits.quota())),
+            config: Arc::new(config),
+            ..AppState::default()
+        });
+
+        let (status, body) = send(
+            app.clone(),
+            Method::PUT,
+            "/store/a",
+            Some(json!("x".repeat(64))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], "body_too_large");
+        let (status, body) = send(app.clone(), Method::PUT, "/store/a", Some(json!([[[1]]]))).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], "json_too_deep");
+
+        // "a" + "[[1]]" and "b" + "1" are 6 and 2 bytes
+        let (status, _) = send(app.clone(), Method::PUT, "/store/a", Some(json!([[1]]))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _) = send(app.clone(), Method::PUT, "/store/b", Some(json!(1))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, body) = send(app.clone(), Method::PUT, "/store/c", Some(json!(1))).await;
+        assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+        assert_eq!(body["code"], "too_many_entries");
+
+        // replacing an entry only counts the difference
+        let (status, body) = send(
+            app.clone(),
+            Method::PUT,
+            "/store/b",
+            Some(json!("x".repeat(12))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+        assert_eq!(body["code"], "storage_full");
+        let (status, _) = send(app, Method::PUT, "/store/b", Some(json!("x".repeat(11)))).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn clients_are_rate_limited_per_scope() {
+        let config = Config {
+            rate_limits: RateLimits {
+                math_per_minute: 2,
+                store_per_minute: 1,
+                api_keys: HashSet::from(["k".to_string()]),
+            },
+            ..Config::default()
+        };
+        let app = build_app(AppState {
+            config: Arc::new(config),
+            ..AppState::default()
+        });
+        let get = |uri: &str, api_key: Option<&str>| {
+            let mut request = client_request().uri(uri);
+            if let Some(api_key) = api_key {
+                request = request.header("x-api-key", api_key);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let response = get("/math/operations", None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["ratelimit-limit"], "2");
+        assert_eq!(response.headers()["ratelimit-remaining"], "1");
+        assert_eq!(
+            get("/math/operations", None).await.unwrap().status(),
+            StatusCode::OK
+        );
+
+        let response = get("/math/operations", None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "30");
+        assert_eq!(response.headers()["ratelimit-remaining"], "0");
+
+        // other clients, other scopes and unscoped routes keep their own budget
+        let response = get("/math/operations", Some("k")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            get("/store/all", None).await.unwrap().status(),
+            StatusCode::OK
+        );
+        let response = get("/store/all", None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "60");
+        let response = get("/metrics", None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key("ratelimit-limit"));
+
+        // made up keys fall back to the peer address, rotating them doesn't help
+        for api_key in ["k2", "k3", &"k".repeat(1000)] {
+            let response = get("/math/operations", Some(api_key)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+    }
+
+    #[tokio::test]
+    async fn limited_routes_need_the_peer_address() {
+        let request = || {
+            Request::builder()
+                .uri("/math/operations")
+                .body(Body::empty())
+        };
+        let response = build_app(AppState::default())
+            .oneshot(request().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["code"], "no_peer_address");
+
+        let config = Config {
+            rate_limits: RateLimits {
+                math_per_minute: 0,
+                ..RateLimits::default()
+            },
+            ..Config::default()
+        };
+        let app = build_app(AppState {
+            config: Arc::new(config),
+            ..AppState::default()
+        });
+        let response = app.oneshot(request().unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,209 @@
+//! Caps on what clients can make the store hold, see `Limits`.
+
+use std::cell::Cell;
+use std::fmt;
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{header, HeaderMap},
+};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+
+use crate::storage::Quota;
+use crate::{ApiError, AppState};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// largest request body, enforced by `DefaultBodyLimit`
+    pub max_body_bytes: usize,
+    /// nested arrays and objects allowed in a stored value, serde_json gives up at 128 anyway
+    pub max_json_depth: usize,
+    pub max_entries: usize,
+    /// keys plus serialized values across the whole store
+    pub max_total_bytes: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_body_bytes: 1024 * 1024,
+            max_json_depth: 32,
+            max_entries: 100_000,
+            max_total_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    /// The part the store enforces itself, atomically with each write.
+    pub fn quota(&self) -> Quota {
+        Quota {
+            max_entries: self.max_entries,
+            max_bytes: self.max_total_bytes,
+        }
+    }
+}
+
+/// Like `Json<Value>`, but rejects values nested deeper than `max_json_depth`
+/// while parsing, before the deep value is ever built.
+pub(crate) struct StoreJson(pub Value);
+
+#[async_trait]
+impl FromRequest<AppState> for StoreJson {
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &AppState) -> Result<Self, ApiError> {
+        if !is_json(request.headers()) {
+            return Err(ApiError::UnsupportedMediaType);
+        }
+        let bytes = Bytes::from_request(request, state).await?;
+
+        let max_depth = state.config.limits.max_json_depth;
+        let exceeded = Cell::new(false);
+        let seed = DepthLimited {
+            remaining: max_depth,
+            exceeded: &exceeded,
+        };
+        let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+        let value = seed
+            .deserialize(&mut deserializer)
+            .and_then(|value| deserializer.end().map(|()| value));
+        match value {
+            Ok(value) => Ok(StoreJson(value)),
+            Err(_) if exceeded.get() => Err(ApiError::TooDeep { max: max_depth }),
+            Err(e) => Err(ApiError::MalformedJson(e)),
+        }
+    }
+}
+
+/// `application/json` or any `+json` type, like axum's `Json`.
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE) else {
+        return false;
+    };
+    let Ok(content_type) = content_type.to_str() else {
+        return false;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+/// Builds a `Value`, failing as soon as more than `remaining` containers nest.
+#[derive(Clone, Copy)]
+struct DepthLimited<'a> {
+    remaining: usize,
+    /// tells a depth failure apart from malformed JSON once serde_json wrapped it
+    exceeded: &'a Cell<bool>,
+}
+
+impl DepthLimited<'_> {
+    fn nested<E: de::Error>(self) -> Result<Self, E> {
+        if self.remaining == 0 {
+            self.exceeded.set(true);
+            return Err(E::custom("JSON nested too deep"));
+        }
+        Ok(DepthLimited {
+            remaining: self.remaining - 1,
+            ..self
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for DepthLimited<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DepthLimited<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let nested = self.nested()?;
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element_seed(nested)? {
+            values.push(value);
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let nested = self.nested()?;
+        let mut values = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let value = map.next_value_seed(nested)?;
+            values.insert(key, value);
+        }
+        Ok(Value::Object(values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str, max_depth: usize) -> Result<Value, bool> {
+        let exceeded = Cell::new(false);
+        let seed = DepthLimited {
+            remaining: max_depth,
+            exceeded: &exceeded,
+        };
+        seed.deserialize(&mut serde_json::Deserializer::from_str(json))
+            .map_err(|_| exceeded.get())
+    }
+
+    #[test]
+    fn depth_counts_nested_containers() {
+        assert_eq!(parse("1", 0), Ok(Value::from(1)));
+        assert_eq!(parse("[1]", 0), Err(true));
+        assert_eq!(
+            parse(r#"{"a": [{"b": null}]}"#, 3).unwrap(),
+            serde_json::json!({"a": [{"b": null}]})
+        );
+        assert_eq!(parse(r#"{"a": [{"b": []}]}"#, 3), Err(true));
+        // malformed input is not a depth failure
+        assert_eq!(parse("[1,", 3), Err(false));
+    }
+}
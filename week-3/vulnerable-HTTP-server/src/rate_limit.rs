@@ -0,0 +1,326 @@
+//! Per-client token buckets, with separate budgets for `/math` and `/store`.
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{ApiError, AppState};
+
+/// how often idle buckets are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// longest `X-Api-Key` accepted, longer ones are never looked up
+pub const MAX_API_KEY_LEN: usize = 128;
+/// buckets kept before the least recently used one is evicted for a new client
+const MAX_BUCKETS: usize = 100_000;
+
+/// Requests per minute, also the burst size. `0` disables limiting.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimits {
+    pub math_per_minute: u32,
+    pub store_per_minute: u32,
+    /// keys that get a bucket of their own, any other `X-Api-Key` is ignored
+    pub api_keys: HashSet<String>,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            math_per_minute: 600,
+            store_per_minute: 120,
+            api_keys: HashSet::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Scope {
+    Math,
+    Store,
+}
+
+impl Scope {
+    fn of(path: &str) -> Option<Scope> {
+        if path == "/math" || path.starts_with("/math/") {
+            Some(Scope::Math)
+        } else if path == "/store" || path.starts_with("/store/") {
+            Some(Scope::Store)
+        } else {
+            None
+        }
+    }
+
+    fn per_minute(self, limits: &RateLimits) -> u32 {
+        match self {
+            Scope::Math => limits.math_per_minute,
+            Scope::Store => limits.store_per_minute,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// the scope's rate, buckets of every scope are swept together
+    per_minute: u32,
+}
+
+impl Bucket {
+    /// Tokens at `now`, uncapped.
+    fn refilled(&self, now: Instant) -> f64 {
+        self.tokens
+            + now.duration_since(self.updated).as_secs_f64() * f64::from(self.per_minute) / 60.0
+    }
+}
+
+/// Outcome of one request against its bucket, rendered as `RateLimit-*` headers.
+#[derive(Debug, PartialEq)]
+struct Decision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    /// seconds until the bucket is full again
+    reset: u64,
+    /// seconds until the next token, only meaningful when not allowed
+    retry_after: u64,
+}
+
+pub struct RateLimiter {
+    inner: Mutex<Buckets>,
+    max_buckets: usize,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter {
+            inner: Mutex::default(),
+            max_buckets: MAX_BUCKETS,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<(Scope, String), Bucket>,
+    last_sweep: Option<Instant>,
+}
+
+impl RateLimiter {
+    fn check(&self, scope: Scope, client: &str, per_minute: u32, now: Instant) -> Decision {
+        let capacity = f64::from(per_minute);
+        let rate = capacity / 60.0;
+        // nothing in here panics, but don't let one that did take limiting down with it
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        if inner
+            .last_sweep
+            .is_none_or(|last| now.duration_since(last) >= SWEEP_INTERVAL)
+        {
+            inner.last_sweep = Some(now);
+            // a bucket that refilled completely is the same as a fresh one
+            inner
+                .buckets
+                .retain(|_, bucket| bucket.refilled(now) < f64::from(bucket.per_minute));
+        }
+
+        let key = (scope, client.to_string());
+        if inner.buckets.len() >= self.max_buckets && !inner.buckets.contains_key(&key) {
+            // linear, but only paid by new clients while the map is full
+            let oldest = inner
+                .buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.updated)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                inner.buckets.remove(&oldest);
+            }
+        }
+        let bucket = inner.buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+            per_minute,
+        });
+        bucket.tokens = bucket.refilled(now).min(capacity);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        Decision {
+            allowed,
+            limit: per_minute,
+            remaining: bucket.tokens as u32,
+            reset: ((capacity - bucket.tokens) / rate).ceil() as u64,
+            retry_after: ((1.0 - bucket.tokens).max(0.0) / rate).ceil() as u64,
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.inner.lock().unwrap().buckets.len()
+    }
+}
+
+/// `X-Api-Key` when it is one of `api_keys`, the peer address otherwise. Unknown keys
+/// and forwarding headers are ignored, any client could make them up. IPv6 peers are
+/// keyed by their /64, a single host usually gets the whole prefix.
+fn client_key(request: &Request, api_keys: &HashSet<String>, peer: SocketAddr) -> String {
+    if let Some(key) = request
+        .headers()
+        .get("x-api-key")
+        .filter(|key| key.len() <= MAX_API_KEY_LEN)
+        .and_then(|key| key.to_str().ok())
+        .filter(|key| api_keys.contains(*key))
+    {
+        return format!("key:{key}");
+    }
+    match peer.ip().to_canonical() {
+        IpAddr::V4(ip) => format!("ip:{ip}"),
+        IpAddr::V6(ip) => {
+            let prefix = Ipv6Addr::from(u128::from(ip) & !u128::from(u64::MAX));
+            format!("ip:{prefix}/64")
+        }
+    }
+}
+
+fn set_headers(headers: &mut HeaderMap, decision: &Decision) {
+    headers.insert("ratelimit-limit", HeaderValue::from(decision.limit));
+    headers.insert("ratelimit-remaining", HeaderValue::from(decision.remaining));
+    headers.insert("ratelimit-reset", HeaderValue::from(decision.reset));
+}
+
+pub(crate) async fn rate_limit(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(scope) = Scope::of(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let per_minute = scope.per_minute(&state.config.rate_limits);
+    if per_minute == 0 {
+        return next.run(request).await;
+    }
+
+    // without the peer address everyone would share one bucket, refuse instead
+    let Some(&ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<SocketAddr>>() else {
+        return ApiError::NoPeerAddress.into_response();
+    };
+    let client = client_key(&request, &state.config.rate_limits.api_keys, peer);
+    let decision = state
+        .rate_limiter
+        .check(scope, &client, per_minute, Instant::now());
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        ApiError::RateLimited {
+            retry_after: decision.retry_after,
+        }
+        .into_response()
+    };
+    set_headers(response.headers_mut(), &decision);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_refill_and_are_separate() {
+        let limiter = RateLimiter::default();
+        let start = Instant::now();
+
+        for remaining in (0..3).rev() {
+            let decision = limiter.check(Scope::Math, "a", 3, start);
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+        let decision = limiter.check(Scope::Math, "a", 3, start);
+        assert!(!decision.allowed);
+        // 3 per minute is a token every 20s
+        assert_eq!(decision.retry_after, 20);
+        assert_eq!(decision.reset, 60);
+
+        assert!(limiter.check(Scope::Store, "a", 3, start).allowed);
+        assert!(limiter.check(Scope::Math, "b", 3, start).allowed);
+        assert!(
+            limiter
+                .check(Scope::Math, "a", 3, start + Duration::from_secs(20))
+                .allowed
+        );
+    }
+
+    #[test]
+    fn idle_buckets_are_evicted() {
+        let limiter = RateLimiter::default();
+        let start = Instant::now();
+        limiter.check(Scope::Math, "a", 60, start);
+        for _ in 0..3 {
+            limiter.check(Scope::Math, "b", 60, start + Duration::from_secs(59));
+        }
+        assert_eq!(limiter.len(), 2);
+
+        // "a" refilled after a second, "b" needs three
+        limiter.check(
+            Scope::Math,
+            "c",
+            60,
+            start + SWEEP_INTERVAL + Duration::from_millis(500),
+        );
+        assert_eq!(limiter.len(), 2);
+    }
+
+    #[test]
+    fn sweep_uses_each_buckets_own_rate() {
+        let limiter = RateLimiter::default();
+        let start = Instant::now();
+        limiter.check(Scope::Store, "a", 120, start);
+        let drained = start + SWEEP_INTERVAL - Duration::from_secs(1);
+        for _ in 0..470 {
+            assert!(limiter.check(Scope::Math, "a", 600, drained).allowed);
+        }
+
+        // the store request sweeps, at 120 a minute the math bucket would look full
+        let swept = start + SWEEP_INTERVAL;
+        limiter.check(Scope::Store, "b", 120, swept);
+        assert_eq!(limiter.check(Scope::Math, "a", 600, swept).remaining, 139);
+    }
+
+    #[test]
+    fn least_recently_used_bucket_makes_room() {
+        let limiter = RateLimiter {
+            max_buckets: 2,
+            ..RateLimiter::default()
+        };
+        let start = Instant::now();
+        limiter.check(Scope::Math, "a", 1, start);
+        limiter.check(Scope::Math, "b", 1, start + Duration::from_millis(1));
+        limiter.check(Scope::Math, "a", 1, start + Duration::from_millis(2));
+
+        // "b" was updated last longest ago, newcomers get buckets of their own
+        let later = start + Duration::from_millis(3);
+        assert!(limiter.check(Scope::Math, "c", 1, later).allowed);
+        assert!(limiter.check(Scope::Math, "d", 1, later).allowed);
+        assert_eq!(limiter.len(), 2);
+        assert!(!limiter.check(Scope::Math, "d", 1, later).allowed);
+    }
+
+    #[test]
+    fn ipv6_peers_are_keyed_by_their_prefix() {
+        let request = Request::new(axum::body::Body::empty());
+        let key = |peer: &str| client_key(&request, &HashSet::new(), peer.parse().unwrap());
+        assert_eq!(key("[2001:db8:1:2:3:4:5:6]:80"), "ip:2001:db8:1:2::/64");
+        assert_eq!(key("[2001:db8:1:2:ffff::1]:80"), "ip:2001:db8:1:2::/64");
+        assert_eq!(key("[::ffff:10.0.0.1]:80"), "ip:10.0.0.1");
+        assert_eq!(key("10.0.0.1:80"), "ip:10.0.0.1");
+    }
+}
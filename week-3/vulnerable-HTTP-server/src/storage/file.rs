@@ -0,0 +1,495 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{KvStore, Quota, SizedMap, StorageError, Usage};
+
+/// logs smaller than this are never compacted
+const MIN_COMPACT_LEN: u64 = 1024 * 1024;
+
+/// One line of the log.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Record {
+    Put { key: String, value: Value },
+    Delete { key: String },
+}
+
+/// `Record::Put` without owning the entry, written by `compact`.
+#[derive(Serialize)]
+#[serde(tag = "op", rename = "put")]
+struct PutRef<'a> {
+    key: &'a str,
+    value: &'a Value,
+}
+
+/// Appends every write as a JSON line and replays the log on open,
+/// reads are served from the replayed map. Overwritten and deleted records are
+/// dropped by rewriting the log on open and whenever it doubles since the last rewrite.
+pub struct FileStore {
+    inner: Arc<Mutex<Inner>>,
+    quota: Quota,
+}
+
+struct Inner {
+    path: PathBuf,
+    log: Box<dyn Log>,
+    /// bytes of complete records, an append that fails is cut back to this
+    len: u64,
+    /// `len` right after the last compaction
+    compacted_len: u64,
+    min_compact_len: u64,
+    /// set when a failed append could not be cut back, no write is safe after that
+    poisoned: bool,
+    entries: SizedMap,
+}
+
+/// What `Inner` needs from the log file, tests swap in one that fails.
+trait Log: Write + Send {
+    fn sync_data(&self) -> io::Result<()>;
+    fn set_len(&self, len: u64) -> io::Result<()>;
+}
+
+impl Log for File {
+    fn sync_data(&self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+}
+
+impl FileStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let path = path.as_ref().to_path_buf();
+        let log = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+
+        let mut data = Vec::new();
+        (&log).read_to_end(&mut data)?;
+        // every acknowledged append ends in a newline, anything after the last one is
+        // a record torn by a crash, cut it off so the next append starts on a fresh line
+        let complete = data.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        if complete < data.len() {
+            log.set_len(complete as u64)?;
+            log.sync_data()?;
+        }
+
+        let mut entries = SizedMap::default();
+        let mut records = 0;
+        for line in data[..complete].split(|&b| b == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            records += 1;
+            // only the tail can be torn, a bad line before it means the log is corrupt
+            match serde_json::from_slice(line)? {
+                Record::Put { key, value } => {
+                    entries.insert(key, value);
+                }
+                Record::Delete { key } => {
+                    entries.remove(&key);
+                }
+            }
+        }
+
+        let mut inner = Inner {
+            path,
+            log: Box::new(log),
+            len: complete as u64,
+            compacted_len: complete as u64,
+            min_compact_len: MIN_COMPACT_LEN,
+            poisoned: false,
+            entries,
+        };
+        if records > inner.entries.usage().entries {
+            inner.compact()?;
+        }
+        Ok(FileStore {
+            inner: Arc::new(Mutex::new(inner)),
+            quota: Quota::default(),
+        })
+    }
+
+    pub fn with_quota(self, quota: Quota) -> Self {
+        FileStore { quota, ..self }
+    }
+
+    /// Runs `f` on the blocking pool, appends write and fsync the log. Once started `f`
+    /// runs to completion even if the request is dropped, so the log and map agree.
+    async fn with_inner<T, F>(&self, f: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Inner) -> Result<T, StorageError> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            // the map is only touched after a successful append, a panic can't tear it
+            let mut inner = inner.lock().unwrap_or_else(|e| e.into_inner());
+            let result = f(&mut inner);
+            inner.compact_if_bloated();
+            result
+        })
+        .await
+        .map_err(|e| StorageError::Io(e.into()))?
+    }
+}
+
+impl Inner {
+    fn append(&mut self, record: &Record) -> Result<(), StorageError> {
+        if self.poisoned {
+            return Err(io::Error::other("log has a torn record, reopen the store").into());
+        }
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if let Err(e) = self
+            .log
+            .write_all(&line)
+            .and_then(|()| self.log.sync_data())
+        {
+            // part of the record may have landed, cut it off so the next acknowledged
+            // append doesn't follow it, the log is opened for append so no seek is needed
+            if self
+                .log
+                .set_len(self.len)
+                .and_then(|()| self.log.sync_data())
+                .is_err()
+            {
+                self.poisoned = true;
+            }
+            return Err(e.into());
+        }
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    fn compact_if_bloated(&mut self) {
+        if self.poisoned || self.len <= self.min_compact_len.max(2 * self.compacted_len) {
+            return;
+        }
+        // the old log is still whole, keep appending to it and retry after the next write
+        if let Err(e) = self.compact() {
+            eprintln!("compacting {} failed: {e}", self.path.display());
+        }
+    }
+
+    /// Rewrites the log as one put per live entry. The new log is written aside and
+    /// renamed over the old one, so a crash leaves either of them whole.
+    fn compact(&mut self) -> Result<(), StorageError> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".compact");
+        let tmp = PathBuf::from(tmp);
+        let len = match self.write_entries(&tmp) {
+            Ok(len) => len,
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                return Err(e);
+            }
+        };
+        fs::rename(&tmp, &self.path)?;
+        // from here on the old handle points at an unlinked file
+        let reopened = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .and_then(|log| {
+                sync_dir(&self.path)?;
+                Ok(log)
+            });
+        match reopened {
+            Ok(log) => self.log = Box::new(log),
+            Err(e) => {
+                self.poisoned = true;
+                return Err(e.into());
+            }
+        }
+        self.len = len;
+        self.compacted_len = len;
+        Ok(())
+    }
+
+    /// Writes every entry to `path` and syncs it, returns the bytes written.
+    fn write_entries(&self, path: &Path) -> Result<u64, StorageError> {
+        let mut out = BufWriter::new(File::create(path)?);
+        for (key, value) in self.entries.iter() {
+            serde_json::to_writer(&mut out, &PutRef { key, value })?;
+            out.write_all(b"\n")?;
+        }
+        let file = out.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok(file.metadata()?.len())
+    }
+}
+
+/// Makes a rename in the directory holding `path` durable.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[async_trait]
+impl KvStore for FileStore {
+    async fn put(&self, key: String, value: Value) -> Result<(), StorageError> {
+        let quota = self.quota;
+        self.with_inner(move |inner| {
+            inner.entries.check_quota(&quota, &key, &value)?;
+            let record = Record::Put { key, value };
+            inner.append(&record)?;
+            if let Record::Put { key, value } = record {
+                inner.entries.insert(key, value);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn insert_new(&self, key: String, value: Value) -> Result<bool, StorageError> {
+        let quota = self.quota;
+        self.with_inner(move |inner| {
+            if inner.entries.contains_key(&key) {
+                return Ok(false);
+            }
+            inner.entries.check_quota(&quota, &key, &value)?;
+            let record = Record::Put { key, value };
+            inner.append(&record)?;
+            if let Record::Put { key, value } = record {
+                inner.entries.insert(key, value);
+            }
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Value>, StorageError> {
+        let key = key.to_string();
+        self.with_inner(move |inner| Ok(inner.entries.get(&key).cloned()))
+            .await
+    }
+
+    async fn scan(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Value)>, StorageError> {
+        let prefix = prefix.to_string();
+        let after = after.map(str::to_string);
+        self.with_inner(move |inner| Ok(inner.entries.scan(&prefix, after.as_deref(), limit)))
+            .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, StorageError> {
+        let key = key.to_string();
+        self.with_inner(move |inner| {
+            if !inner.entries.contains_key(&key) {
+                return Ok(false);
+            }
+            inner.append(&Record::Delete { key: key.clone() })?;
+            inner.entries.remove(&key);
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        current: &Value,
+        new: Option<Value>,
+    ) -> Result<bool, StorageError> {
+        let key = key.to_string();
+        let current = current.clone();
+        let quota = self.quota;
+        self.with_inner(move |inner| {
+            if inner.entries.get(&key) != Some(&current) {
+                return Ok(false);
+            }
+            match new {
+                Some(value) => {
+                    inner.entries.check_quota(&quota, &key, &value)?;
+                    let record = Record::Put { key, value };
+                    inner.append(&record)?;
+                    if let Record::Put { key, value } = record {
+                        inner.entries.insert(key, value);
+                    }
+                }
+                None => {
+                    inner.append(&Record::Delete { key: key.clone() })?;
+                    inner.entries.remove(&key);
+                }
+            }
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn usage(&self) -> Result<Usage, StorageError> {
+        self.with_inner(|inner| Ok(inner.entries.usage())).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::entry_size;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn entries_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+
+        let store = FileStore::open(&path).unwrap();
+        store.put("a".into(), json!(1)).await.unwrap();
+        store.put("b".into(), json!({"x": [1, 2]})).await.unwrap();
+        store.put("a".into(), json!(2)).await.unwrap();
+        assert!(store.delete("b").await.unwrap());
+        assert!(!store.compare_and_swap("a", &json!(1), None).await.unwrap());
+        assert!(store
+            .compare_and_swap("a", &json!(2), Some(json!(3)))
+            .await
+            .unwrap());
+        drop(store);
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(
+            store.usage().await.unwrap().bytes,
+            entry_size("a", &json!(3))
+        );
+        assert_eq!(
+            store.scan("", None, 10).await.unwrap(),
+            vec![("a".to_string(), json!(3))]
+        );
+    }
+
+    #[tokio::test]
+    async fn torn_tail_is_truncated_before_appending() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+        std::fs::write(
+            &path,
+            "{\"op\":\"put\",\"key\":\"a\",\"value\":1}\n{\"op\":\"put\",\"ke",
+        )
+        .unwrap();
+
+        let store = FileStore::open(&path).unwrap();
+        store.put("b".into(), json!(2)).await.unwrap();
+        drop(store);
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(
+            store.scan("", None, 10).await.unwrap(),
+            vec![("a".to_string(), json!(1)), ("b".to_string(), json!(2))]
+        );
+    }
+
+    #[tokio::test]
+    async fn overwrites_do_not_grow_the_log_forever() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+        let store = FileStore::open(&path).unwrap();
+        store.inner.lock().unwrap().min_compact_len = 1024;
+        store
+            .put("keep".into(), json!("x".repeat(100)))
+            .await
+            .unwrap();
+        for i in 0..500 {
+            store.put("a".into(), json!(i)).await.unwrap();
+            assert!(store.delete("a").await.unwrap());
+        }
+        assert!(std::fs::metadata(&path).unwrap().len() <= 2 * 1024 + 100);
+        drop(store);
+
+        // reopening drops what the last compaction didn't
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert_eq!(
+            store.scan("", None, 10).await.unwrap(),
+            vec![("keep".to_string(), json!("x".repeat(100)))]
+        );
+    }
+
+    /// Forwards to the real log, but the first write only gets half its bytes through.
+    struct FailOnce {
+        file: File,
+        failed: bool,
+    }
+
+    impl Write for FailOnce {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.failed {
+                return self.file.write(buf);
+            }
+            self.failed = true;
+            self.file.write_all(&buf[..buf.len() / 2])?;
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.file.flush()
+        }
+    }
+
+    impl Log for FailOnce {
+        fn sync_data(&self) -> io::Result<()> {
+            self.file.sync_data()
+        }
+
+        fn set_len(&self, len: u64) -> io::Result<()> {
+            self.file.set_len(len)
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_append_is_cut_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+        let store = FileStore::open(&path).unwrap();
+        store.put("a".into(), json!(1)).await.unwrap();
+        {
+            let mut inner = store.inner.lock().unwrap();
+            let file = OpenOptions::new().append(true).open(&path).unwrap();
+            inner.log = Box::new(FailOnce {
+                file,
+                failed: false,
+            });
+        }
+
+        assert!(matches!(
+            store.put("b".into(), json!("lost")).await,
+            Err(StorageError::Io(_))
+        ));
+        store.put("b".into(), json!(2)).await.unwrap();
+        drop(store);
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(
+            store.scan("", None, 10).await.unwrap(),
+            vec![("a".to_string(), json!(1)), ("b".to_string(), json!(2))]
+        );
+    }
+
+    #[test]
+    fn corrupt_line_before_the_tail_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+        std::fs::write(
+            &path,
+            "{\"op\":\"put\",\"key\":\"a\",\"value\":1}\ngarbage\n{\"op\":\"delete\",\"key\":\"a\"}\n",
+        )
+        .unwrap();
+
+        assert!(matches!(FileStore::open(&path), Err(StorageError::Json(_))));
+    }
+}
@@ -0,0 +1,81 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use super::{KvStore, Quota, SizedMap, StorageError, Usage};
+
+/// Lost on restart, the default.
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: RwLock<SizedMap>,
+    quota: Quota,
+}
+
+impl MemoryStore {
+    pub fn with_quota(self, quota: Quota) -> Self {
+        MemoryStore { quota, ..self }
+    }
+}
+
+#[async_trait]
+impl KvStore for MemoryStore {
+    async fn put(&self, key: String, value: Value) -> Result<(), StorageError> {
+        let mut entries = self.entries.write().await;
+        entries.check_quota(&self.quota, &key, &value)?;
+        entries.insert(key, value);
+        Ok(())
+    }
+
+    async fn insert_new(&self, key: String, value: Value) -> Result<bool, StorageError> {
+        let mut entries = self.entries.write().await;
+        if entries.contains_key(&key) {
+            return Ok(false);
+        }
+        entries.check_quota(&self.quota, &key, &value)?;
+        entries.insert(key, value);
+        Ok(true)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Value>, StorageError> {
+        Ok(self.entries.read().await.get(key).cloned())
+    }
+
+    async fn scan(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Value)>, StorageError> {
+        Ok(self.entries.read().await.scan(prefix, after, limit))
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, StorageError> {
+        Ok(self.entries.write().await.remove(key).is_some())
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        current: &Value,
+        new: Option<Value>,
+    ) -> Result<bool, StorageError> {
+        let mut entries = self.entries.write().await;
+        if entries.get(key) != Some(current) {
+            return Ok(false);
+        }
+        match new {
+            Some(value) => {
+                entries.check_quota(&self.quota, key, &value)?;
+                entries.insert(key.to_string(), value);
+            }
+            None => {
+                entries.remove(key);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn usage(&self) -> Result<Usage, StorageError> {
+        Ok(self.entries.read().await.usage())
+    }
+}
@@ -0,0 +1,304 @@
+//! Key-value backends for the JSON store, picked at startup with `--storage`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+mod file;
+mod memory;
+mod sqlite;
+
+pub use file::FileStore;
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+/// `put`, `insert_new` and `compare_and_swap` check the store's `Quota` atomically
+/// with the write and fail with `TooManyEntries` or `Full` instead of exceeding it.
+#[async_trait]
+pub trait KvStore: Send + Sync {
+    /// Inserts or replaces the value under `key`.
+    async fn put(&self, key: String, value: Value) -> Result<(), StorageError>;
+    /// Inserts only if `key` is absent, atomically, returns whether it did.
+    async fn insert_new(&self, key: String, value: Value) -> Result<bool, StorageError>;
+    async fn get(&self, key: &str) -> Result<Option<Value>, StorageError>;
+    /// Up to `limit` entries whose key starts with `prefix` and sorts after `after`,
+    /// ordered by key.
+    async fn scan(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Value)>, StorageError>;
+    /// Returns whether `key` existed.
+    async fn delete(&self, key: &str) -> Result<bool, StorageError>;
+    /// Replaces, or with `None` deletes, the entry only if it still holds `current`,
+    /// atomically, returns whether it did.
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        current: &Value,
+        new: Option<Value>,
+    ) -> Result<bool, StorageError>;
+    async fn usage(&self) -> Result<Usage, StorageError>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub entries: usize,
+    /// keys plus serialized values, see `entry_size`
+    pub bytes: u64,
+}
+
+/// Caps on what a store holds, checked by every write under the same lock as the write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    pub max_entries: usize,
+    /// see `entry_size`
+    pub max_bytes: u64,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Quota {
+            max_entries: usize::MAX,
+            max_bytes: u64::MAX,
+        }
+    }
+}
+
+impl Quota {
+    /// Fails if replacing an entry of `old` bytes, or adding one if `None`, with one
+    /// of `new` bytes would take `usage` past the quota.
+    fn check(&self, usage: Usage, old: Option<u64>, new: u64) -> Result<(), StorageError> {
+        let entries = usage.entries + usize::from(old.is_none());
+        if entries > self.max_entries {
+            return Err(StorageError::TooManyEntries {
+                max: self.max_entries,
+            });
+        }
+        let bytes = usage.bytes - old.unwrap_or(0) + new;
+        if bytes > self.max_bytes {
+            return Err(StorageError::Full {
+                max: self.max_bytes,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// What an entry counts against `--max-total-bytes`.
+pub fn entry_size(key: &str, value: &Value) -> u64 {
+    struct Counter(u64);
+    impl Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len() as u64;
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let mut counter = Counter(key.len() as u64);
+    // writing a `Value` into an infallible sink can't fail
+    serde_json::to_writer(&mut counter, value).unwrap();
+    counter.0
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Sqlite(rusqlite::Error),
+    TooManyEntries { max: usize },
+    Full { max: u64 },
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "io error: {e}"),
+            StorageError::Json(e) => write!(f, "corrupt entry: {e}"),
+            StorageError::Sqlite(e) => write!(f, "sqlite error: {e}"),
+            StorageError::TooManyEntries { max } => write!(f, "store is limited to {max} entries"),
+            StorageError::Full { max } => write!(f, "store is limited to {max} bytes"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Json(e)
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError::Sqlite(e)
+    }
+}
+
+/// `memory`, `file:<path>` or `sqlite:<path>`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum StorageConfig {
+    #[default]
+    Memory,
+    File(PathBuf),
+    Sqlite(PathBuf),
+}
+
+impl FromStr for StorageConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            _ if s == "memory" => Ok(StorageConfig::Memory),
+            Some(("file", path)) if !path.is_empty() => Ok(StorageConfig::File(path.into())),
+            Some(("sqlite", path)) if !path.is_empty() => Ok(StorageConfig::Sqlite(path.into())),
+            _ => Err(format!(
+                "invalid storage `{s}`, expected memory, file:<path> or sqlite:<path>"
+            )),
+        }
+    }
+}
+
+/// In-memory entries with a running `Usage`, shared by the map backed stores.
+#[derive(Default)]
+struct SizedMap {
+    entries: BTreeMap<String, Value>,
+    bytes: u64,
+}
+
+impl SizedMap {
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.get(key)
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    fn insert(&mut self, key: String, value: Value) {
+        let size = entry_size(&key, &value);
+        if let Some(old) = self.entries.get(&key) {
+            self.bytes -= entry_size(&key, old);
+        }
+        self.bytes += size;
+        self.entries.insert(key, value);
+    }
+
+    /// Fails if storing `value` under `key` would take the map past `quota`.
+    fn check_quota(&self, quota: &Quota, key: &str, value: &Value) -> Result<(), StorageError> {
+        let old = self.entries.get(key).map(|old| entry_size(key, old));
+        quota.check(self.usage(), old, entry_size(key, value))
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Value> {
+        let old = self.entries.remove(key)?;
+        self.bytes -= entry_size(key, &old);
+        Some(old)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.entries.iter()
+    }
+
+    fn scan(&self, prefix: &str, after: Option<&str>, limit: usize) -> Vec<(String, Value)> {
+        let start = match after {
+            Some(after) if after >= prefix => Bound::Excluded(after),
+            _ => Bound::Included(prefix),
+        };
+        self.entries
+            .range::<str, _>((start, Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .take(limit)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            entries: self.entries.len(),
+            bytes: self.bytes,
+        }
+    }
+}
+
+pub fn open(config: &StorageConfig, quota: Quota) -> Result<Arc<dyn KvStore>, StorageError> {
+    Ok(match config {
+        StorageConfig::Memory => Arc::new(MemoryStore::default().with_quota(quota)),
+        StorageConfig::File(path) => Arc::new(FileStore::open(path)?.with_quota(quota)),
+        StorageConfig::Sqlite(path) => Arc::new(SqliteStore::open(path)?.with_quota(quota)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn quota_holds_under_concurrent_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let quota = Quota {
+            max_entries: 5,
+            max_bytes: 1_000,
+        };
+        let stores: Vec<Arc<dyn KvStore>> = vec![
+            Arc::new(MemoryStore::default().with_quota(quota)),
+            Arc::new(
+                FileStore::open(dir.path().join("store.log"))
+                    .unwrap()
+                    .with_quota(quota),
+            ),
+            Arc::new(
+                SqliteStore::open(dir.path().join("store.db"))
+                    .unwrap()
+                    .with_quota(quota),
+            ),
+        ];
+        for store in stores {
+            let writers = (0..50).map(|i| {
+                let store = store.clone();
+                tokio::spawn(async move { store.insert_new(format!("k{i}"), json!(i)).await })
+            });
+            let mut full = 0;
+            for writer in writers.collect::<Vec<_>>() {
+                match writer.await.unwrap() {
+                    Ok(inserted) => assert!(inserted),
+                    Err(StorageError::TooManyEntries { max: 5 }) => full += 1,
+                    Err(e) => panic!("unexpected error: {e}"),
+                }
+            }
+            assert_eq!(full, 45);
+            assert_eq!(store.usage().await.unwrap().entries, 5);
+
+            // growing an entry counts only the difference, shrinking always fits
+            let (key, value) = store.scan("", None, 1).await.unwrap().remove(0);
+            let room = 1_000 - store.usage().await.unwrap().bytes + entry_size(&key, &value);
+            let too_big = json!("x".repeat(room as usize - key.len()));
+            assert!(matches!(
+                store.put(key.clone(), too_big).await,
+                Err(StorageError::Full { max: 1_000 })
+            ));
+            assert!(store
+                .compare_and_swap(&key, &value, Some(json!(null)))
+                .await
+                .unwrap());
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+
+use super::{KvStore, Quota, StorageError, Usage};
+
+/// Values are stored as JSON text in a single `entries` table.
+pub struct SqliteStore {
+    db: Arc<Mutex<Db>>,
+    quota: Quota,
+}
+
+/// The connection and a running `Usage`, so writes don't have to sum the table.
+struct Db {
+    conn: Connection,
+    usage: Usage,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let conn = Connection::open(path)?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS entries (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+            [],
+        )?;
+        // values are stored exactly as `entry_size` serializes them, so the sums agree
+        let (entries, bytes): (i64, i64) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(length(CAST(key AS BLOB)) + length(CAST(value AS BLOB))), 0)
+             FROM entries",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let usage = Usage {
+            entries: entries as usize,
+            bytes: bytes as u64,
+        };
+        Ok(SqliteStore {
+            db: Arc::new(Mutex::new(Db { conn, usage })),
+            quota: Quota::default(),
+        })
+    }
+
+    pub fn with_quota(self, quota: Quota) -> Self {
+        SqliteStore { quota, ..self }
+    }
+
+    /// Runs `f` on the blocking pool, rusqlite calls would stall the runtime.
+    async fn with_db<T, F>(&self, f: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Db) -> Result<T, StorageError> + Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || {
+            // `usage` is only updated after a statement succeeds, a panic can't skew it
+            let mut db = db.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut db)
+        })
+        .await
+        .map_err(|e| StorageError::Io(e.into()))?
+    }
+}
+
+impl Db {
+    /// `entry_size` of what is stored under `key`, without parsing the value.
+    fn stored_size(&self, key: &str) -> Result<Option<u64>, StorageError> {
+        let len: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT length(CAST(value AS BLOB)) FROM entries WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(len.map(|len| (key.len() + len as usize) as u64))
+    }
+
+    /// Swaps an entry of `old` bytes, or none, for one of `new` bytes, or none.
+    fn account(&mut self, old: Option<u64>, new: Option<u64>) {
+        self.usage.entries =
+            self.usage.entries + usize::from(new.is_some()) - usize::from(old.is_some());
+        self.usage.bytes = self.usage.bytes + new.unwrap_or(0) - old.unwrap_or(0);
+    }
+}
+
+#[async_trait]
+impl KvStore for SqliteStore {
+    async fn put(&self, key: String, value: Value) -> Result<(), StorageError> {
+        let value = serde_json::to_string(&value)?;
+        let quota = self.quota;
+        // the connection mutex serializes every statement, so check then write is atomic
+        self.with_db(move |db| {
+            let old = db.stored_size(&key)?;
+            let new = (key.len() + value.len()) as u64;
+            quota.check(db.usage, old, new)?;
+            db.conn.execute(
+                "INSERT INTO entries (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )?;
+            db.account(old, Some(new));
+            Ok(())
+        })
+        .await
+    }
+
+    async fn insert_new(&self, key: String, value: Value) -> Result<bool, StorageError> {
+        let value = serde_json::to_string(&value)?;
+        let quota = self.quota;
+        self.with_db(move |db| {
+            if db.stored_size(&key)?.is_some() {
+                return Ok(false);
+            }
+            let new = (key.len() + value.len()) as u64;
+            quota.check(db.usage, None, new)?;
+            db.conn.execute(
+                "INSERT INTO entries (key, value) VALUES (?1, ?2)",
+                params![key, value],
+            )?;
+            db.account(None, Some(new));
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Value>, StorageError> {
+        let key = key.to_string();
+        self.with_db(move |db| {
+            let value: Option<String> = db
+                .conn
+                .query_row(
+                    "SELECT value FROM entries WHERE key = ?1",
+                    params![key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(value.map(|v| serde_json::from_str(&v)).transpose()?)
+        })
+        .await
+    }
+
+    async fn scan(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Value)>, StorageError> {
+        let prefix = prefix.to_string();
+        let after = after.map(str::to_string);
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        self.with_db(move |db| {
+            // TEXT compares bytewise like `String`, so pages line up with the other stores
+            let mut stmt = db.conn.prepare(
+                "SELECT key, value FROM entries
+                 WHERE key >= ?1 AND substr(key, 1, length(?1)) = ?1
+                   AND (?2 IS NULL OR key > ?2)
+                 ORDER BY key LIMIT ?3",
+            )?;
+            let rows = stmt.query_map(params![prefix, after, limit], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            let mut entries = Vec::new();
+            for row in rows {
+                let (key, value) = row?;
+                entries.push((key, serde_json::from_str(&value)?));
+            }
+            Ok(entries)
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, StorageError> {
+        let key = key.to_string();
+        self.with_db(move |db| {
+            let Some(old) = db.stored_size(&key)? else {
+                return Ok(false);
+            };
+            db.conn
+                .execute("DELETE FROM entries WHERE key = ?1", params![key])?;
+            db.account(Some(old), None);
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        current: &Value,
+        new: Option<Value>,
+    ) -> Result<bool, StorageError> {
+        let key = key.to_string();
+        let current = current.clone();
+        let new = new.map(|value| serde_json::to_string(&value)).transpose()?;
+        let quota = self.quota;
+        self.with_db(move |db| {
+            let stored: Option<String> = db
+                .conn
+                .query_row(
+                    "SELECT value FROM entries WHERE key = ?1",
+                    params![key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let Some(stored) = stored else {
+                return Ok(false);
+            };
+            if serde_json::from_str::<Value>(&stored)? != current {
+                return Ok(false);
+            }
+            let old = (key.len() + stored.len()) as u64;
+            match new {
+                Some(value) => {
+                    let new = (key.len() + value.len()) as u64;
+                    quota.check(db.usage, Some(old), new)?;
+                    db.conn.execute(
+                        "UPDATE entries SET value = ?2 WHERE key = ?1",
+                        params![key, value],
+                    )?;
+                    db.account(Some(old), Some(new));
+                }
+                None => {
+                    db.conn
+                        .execute("DELETE FROM entries WHERE key = ?1", params![key])?;
+                    db.account(Some(old), None);
+                }
+            }
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn usage(&self) -> Result<Usage, StorageError> {
+        self.with_db(|db| Ok(db.usage)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::entry_size;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn entries_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.db");
+
+        let store = SqliteStore::open(&path).unwrap();
+        store.put("b".into(), json!([1, 2])).await.unwrap();
+        store.put("a".into(), json!(1)).await.unwrap();
+        store.put("a".into(), json!(2)).await.unwrap();
+        assert!(!store.insert_new("a".into(), json!(3)).await.unwrap());
+        assert!(!store.compare_and_swap("a", &json!(1), None).await.unwrap());
+        assert!(store
+            .compare_and_swap("b", &json!([1, 2]), Some(json!([3])))
+            .await
+            .unwrap());
+        assert!(!store.delete("missing").await.unwrap());
+        drop(store);
+
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(
+            store.usage().await.unwrap(),
+            Usage {
+                entries: 2,
+                bytes: entry_size("a", &json!(2)) + entry_size("b", &json!([3])),
+            }
+        );
+        assert_eq!(store.get("a").await.unwrap(), Some(json!(2)));
+        assert_eq!(
+            store.scan("", None, 10).await.unwrap(),
+            vec![("a".to_string(), json!(2)), ("b".to_string(), json!([3]))]
+        );
+        store.put("ab".into(), json!(4)).await.unwrap();
+        assert_eq!(
+            store.scan("a", Some("a"), 10).await.unwrap(),
+            vec![("ab".to_string(), json!(4))]
+        );
+    }
+}
@@ -1,5 +1,4 @@
-use reqwest;
-use serde_json::{json, Value, Number};
+use serde_json::{Value, Number};
 
 #[tokio::main]
 async fn main() {
@@ -0,0 +1,437 @@
+//! The `/store` routes, a JSON document per key with ETag based concurrency control.
+
+use std::sync::Arc;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{rejection::QueryRejection, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::limits::StoreJson;
+use crate::storage::{KvStore, StorageError};
+use crate::{ApiError, AppState};
+
+pub(crate) const MAX_KEY_LEN: usize = 256;
+/// `/store/all` shadows a key of the same name
+pub(crate) const RESERVED_KEY: &str = "all";
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1_000;
+/// entries fetched from storage per NDJSON chunk
+const STREAM_CHUNK_SIZE: usize = 100;
+
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/store", post(store_data))
+        .route("/store/all", get(retrieve_all))
+        // axum answers HEAD from the GET handler, dropping the body
+        .route(
+            "/store/:key",
+            get(get_data)
+                .put(put_data)
+                .patch(patch_data)
+                .delete(delete_data),
+        )
+}
+
+pub(crate) fn validate_key(key: &str) -> Result<(), ApiError> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN || key == RESERVED_KEY {
+        return Err(ApiError::InvalidKey);
+    }
+    Ok(())
+}
+
+/// Strong ETag of a stored value, FNV-1a over its serialization so it stays
+/// the same across restarts and builds.
+fn etag(value: &Value) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in value.to_string().bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("\"{hash:016x}\"")
+}
+
+/// Whether an `If-Match` / `If-None-Match` list names `etag` (or is `*`).
+fn etag_matches(header: &str, etag: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+/// Fails with 412 unless `If-Match` is absent or names the current value.
+fn check_if_match(headers: &HeaderMap, current: Option<&Value>) -> Result<(), ApiError> {
+    let Some(if_match) = headers.get(header::IF_MATCH) else {
+        return Ok(());
+    };
+    let if_match = if_match.to_str().unwrap_or_default();
+    match current {
+        Some(current) if etag_matches(if_match, &etag(current)) => Ok(()),
+        _ => Err(ApiError::PreconditionFailed),
+    }
+}
+
+/// 201 pointing at the new entry.
+fn created(key: &str, value: &Value) -> Response {
+    // keys are percent-decoded path segments, encode them back for the header
+    let location = format!("/store/{}", encode_path_segment(key));
+    (
+        StatusCode::CREATED,
+        [(header::LOCATION, location), (header::ETAG, etag(value))],
+        format!("Data stored with key: {key}"),
+    )
+        .into_response()
+}
+
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// RFC 7396 JSON Merge Patch, `null` members delete.
+fn merge_patch(target: &mut Value, patch: Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!()
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            merge_patch(target.entry(key).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Stores under a fresh UUIDv7, which sorts by creation time.
+async fn store_data(
+    State(state): State<AppState>,
+    StoreJson(data): StoreJson,
+) -> Result<Response, ApiError> {
+    let key = Uuid::now_v7().to_string();
+    // a collision would need the same millisecond and 74 random bits, never overwrite anyway
+    if !state.storage.insert_new(key.clone(), data.clone()).await? {
+        return Err(ApiError::KeyExists);
+    }
+    Ok(created(&key, &data))
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum ListFormat {
+    #[default]
+    Json,
+    Ndjson,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ListQuery {
+    limit: Option<usize>,
+    /// `next_cursor` of the previous page
+    cursor: Option<String>,
+    #[serde(default)]
+    prefix: String,
+    #[serde(default)]
+    format: ListFormat,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    key: String,
+    value: Value,
+}
+
+#[derive(Serialize)]
+struct Page {
+    entries: Vec<Entry>,
+    /// absent on the last page
+    next_cursor: Option<String>,
+}
+
+/// Lists entries in key order, a page at a time or, with `format=ndjson`,
+/// streamed one entry per line.
+async fn retrieve_all(
+    State(state): State<AppState>,
+    query: Result<Query<ListQuery>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(query) = query?;
+    if query.format == ListFormat::Ndjson {
+        return Ok(stream_entries(state.storage, query));
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    // one extra entry tells whether another page follows
+    let mut entries = state
+        .storage
+        .scan(&query.prefix, query.cursor.as_deref(), limit + 1)
+        .await?;
+    let next_cursor = if entries.len() > limit {
+        entries.truncate(limit);
+        entries.last().map(|(key, _)| key.clone())
+    } else {
+        None
+    };
+
+    let entries = entries
+        .into_iter()
+        .map(|(key, value)| Entry { key, value })
+        .collect();
+    Ok(Json(Page {
+        entries,
+        next_cursor,
+    })
+    .into_response())
+}
+
+struct StreamState {
+    storage: Arc<dyn KvStore>,
+    prefix: String,
+    after: Option<String>,
+    /// `limit` left to send, unbounded if absent
+    remaining: Option<usize>,
+    done: bool,
+}
+
+/// Streams matching entries as NDJSON, fetching them in chunks so storage locks
+/// are only held per chunk and never while the client reads.
+fn stream_entries(storage: Arc<dyn KvStore>, query: ListQuery) -> Response {
+    let state = StreamState {
+        storage,
+        prefix: query.prefix,
+        after: query.cursor,
+        remaining: query.limit,
+        done: false,
+    };
+    let chunks = stream::unfold(state, |mut state| async move {
+        let chunk_size = state.remaining.unwrap_or(usize::MAX).min(STREAM_CHUNK_SIZE);
+        if state.done || chunk_size == 0 {
+            return None;
+        }
+        let entries = match state
+            .storage
+            .scan(&state.prefix, state.after.as_deref(), chunk_size)
+            .await
+        {
+            Ok(entries) => entries,
+            Err(e) => {
+                // the status is already sent, cutting the body short is all that's left
+                state.done = true;
+                return Some((Err(e), state));
+            }
+        };
+        if entries.is_empty() {
+            return None;
+        }
+        state.done = entries.len() < chunk_size;
+        state.remaining = state.remaining.map(|r| r - entries.len());
+        state.after = entries.last().map(|(key, _)| key.clone());
+
+        let mut chunk = Vec::new();
+        for (key, value) in entries {
+            // `Entry` only holds strings and JSON values, serializing can't fail
+            serde_json::to_writer(&mut chunk, &Entry { key, value }).unwrap();
+            chunk.push(b'\n');
+        }
+        Some((Ok::<_, StorageError>(Bytes::from(chunk)), state))
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+async fn get_data(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let value = state.storage.get(&key).await?.ok_or(ApiError::NotFound)?;
+    let etag = etag(&value);
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| etag_matches(h, &etag));
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    Ok(([(header::ETAG, etag)], Json(value)).into_response())
+}
+
+/// Stores under a client chosen key. `If-None-Match: *` only creates,
+/// `If-Match` only replaces the version the client last saw.
+async fn put_data(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    StoreJson(data): StoreJson,
+) -> Result<Response, ApiError> {
+    validate_key(&key)?;
+    let create_only = headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| value.as_bytes() == b"*");
+
+    if headers.contains_key(header::IF_MATCH) {
+        let current = state.storage.get(&key).await?;
+        check_if_match(&headers, current.as_ref())?;
+        let current = current.ok_or(ApiError::PreconditionFailed)?;
+        if !state
+            .storage
+            .compare_and_swap(&key, &current, Some(data.clone()))
+            .await?
+        {
+            return Err(ApiError::Conflict);
+        }
+        return Ok(replaced(&data));
+    }
+
+    if state.storage.insert_new(key.clone(), data.clone()).await? {
+        return Ok(created(&key, &data));
+    }
+    if create_only {
+        return Err(ApiError::KeyExists);
+    }
+    state.storage.put(key, data.clone()).await?;
+    Ok(replaced(&data))
+}
+
+fn replaced(value: &Value) -> Response {
+    (StatusCode::NO_CONTENT, [(header::ETAG, etag(value))]).into_response()
+}
+
+/// Applies a merge patch, failing with 409 if the entry changed while merging.
+async fn patch_data(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    StoreJson(patch): StoreJson,
+) -> Result<Response, ApiError> {
+    let current = state.storage.get(&key).await?.ok_or(ApiError::NotFound)?;
+    check_if_match(&headers, Some(&current))?;
+
+    let mut patched = current.clone();
+    merge_patch(&mut patched, patch);
+    if !state
+        .storage
+        .compare_and_swap(&key, &current, Some(patched.clone()))
+        .await?
+    {
+        return Err(ApiError::Conflict);
+    }
+    Ok(([(header::ETAG, etag(&patched))], Json(patched)).into_response())
+}
+
+async fn delete_data(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    if !headers.contains_key(header::IF_MATCH) {
+        return match state.storage.delete(&key).await? {
+            true => Ok(StatusCode::NO_CONTENT),
+            false => Err(ApiError::NotFound),
+        };
+    }
+
+    let current = state.storage.get(&key).await?.ok_or(ApiError::NotFound)?;
+    check_if_match(&headers, Some(&current))?;
+    if !state.storage.compare_and_swap(&key, &current, None).await? {
+        return Err(ApiError::Conflict);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merge_patch_follows_rfc_7396() {
+        // the examples from RFC 7396 appendix A
+        let cases = [
+            (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+            (
+                json!({"a": "b"}),
+                json!({"b": "c"}),
+                json!({"a": "b", "b": "c"}),
+            ),
+            (json!({"a": "b"}), json!({"a": null}), json!({})),
+            (
+                json!({"a": "b", "b": "c"}),
+                json!({"a": null}),
+                json!({"b": "c"}),
+            ),
+            (json!({"a": ["b"]}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "c"}), json!({"a": ["b"]}), json!({"a": ["b"]})),
+            (
+                json!({"a": {"b": "c"}}),
+                json!({"a": {"b": "d", "c": null}}),
+                json!({"a": {"b": "d"}}),
+            ),
+            (
+                json!({"a": [{"b": "c"}]}),
+                json!({"a": [1]}),
+                json!({"a": [1]}),
+            ),
+            (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+            (json!({"a": "b"}), json!(["c"]), json!(["c"])),
+            (json!({"a": "foo"}), json!(null), json!(null)),
+            (json!({"a": "foo"}), json!("bar"), json!("bar")),
+            (
+                json!({"e": null}),
+                json!({"a": 1}),
+                json!({"e": null, "a": 1}),
+            ),
+            (
+                json!([1, 2]),
+                json!({"a": "b", "c": null}),
+                json!({"a": "b"}),
+            ),
+            (
+                json!({}),
+                json!({"a": {"bb": {"ccc": null}}}),
+                json!({"a": {"bb": {}}}),
+            ),
+        ];
+        for (mut target, patch, expected) in cases {
+            merge_patch(&mut target, patch);
+            assert_eq!(target, expected);
+        }
+    }
+
+    #[test]
+    fn etag_lists_match() {
+        let tag = etag(&json!({"a": 1}));
+        assert!(etag_matches(&tag, &tag));
+        assert!(etag_matches(&format!("\"other\", W/{tag}"), &tag));
+        assert!(etag_matches("*", &tag));
+        assert!(!etag_matches("\"other\"", &tag));
+    }
+}